@@ -93,7 +93,7 @@ impl FromStr for CrawlDomain {
     }
 }
 
-impl<'a> TryFrom<&'a str> for CrawlDomain {
+impl TryFrom<&str> for CrawlDomain {
     type Error = url::ParseError;
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         CrawlDomain::from_str(value)
@@ -1,9 +1,8 @@
 use crate::crawl_domain::CrawlDomain;
 use crate::crawler_api::domains::{Domains, Urls};
 use futures::stream::StreamExt;
-use reqwest::Client;
 use warp::Rejection;
-use web_crawler_lib::crawl_domain_with_client;
+use web_crawler_lib::Crawler;
 
 /// Error case for when a Domain is posted that already exists in our collection.
 #[derive(Debug)]
@@ -22,7 +21,7 @@ impl warp::reject::Reject for SchemeNotSupported {}
 pub async fn add_domain(
     domains: Domains,
     domain_key: CrawlDomain,
-    client: Client,
+    crawler: Crawler,
 ) -> Result<CrawlDomain, Rejection> {
     // Only support crawls on http and https domains.
     if domain_key.as_ref().scheme() != "http" && domain_key.as_ref().scheme() != "https" {
@@ -43,14 +42,18 @@ pub async fn add_domain(
             let _crawl_guard = CrawlCompleted { urls: urls.clone() };
 
             // If we can't connect to the address then early-exit
-            if client.head(url.clone()).send().await.is_err() {
+            if crawler.probe(url.clone()).await.is_err() {
                 return;
             }
 
             // Iterate through the stream, adding the URL to our domain's list.
-            crawl_domain_with_client(client, url)
+            crawler
+                .crawl(url)
                 .for_each_concurrent(None, move |crawl_result| {
-                    urls.write().unwrap().urls.push(crawl_result.url);
+                    // URLs skipped by the crawl were never visited.
+                    if crawl_result.is_visited() {
+                        urls.write().unwrap().urls.push(crawl_result.url);
+                    }
                     futures::future::ready(())
                 })
                 .await;
@@ -61,7 +61,7 @@ impl UrlListCountResult {
     pub fn create(domain_urls: &DomainUrls) -> Self {
         Self {
             crawl_completed: domain_urls.crawl_completed,
-            url_count: domain_urls.urls.iter().count(),
+            url_count: domain_urls.urls.len(),
         }
     }
 }
@@ -4,18 +4,23 @@ mod tests;
 
 use crawl_domain::CrawlDomain;
 use crawler_api::*;
-use reqwest::Client;
 use std::env;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 use warp::Filter;
+use web_crawler_lib::{CrawlerBuilder, WarcOptions, WarcWriter, DEFAULT_HOST_LIMITS};
 
 #[tokio::main]
 async fn main() {
     let ip = get_command_line_socket_addr();
     println!("Started crawler server at {}", ip);
+    let warc = get_command_line_warc_dir().map(|dir| {
+        println!("Archiving crawls to {}", dir.display());
+        WarcWriter::new(dir, WarcOptions::default())
+    });
 
     let crawler = build_crawler_domains();
-    let crawler_routes = build_post_domain(crawler.clone())
+    let crawler_routes = build_post_domain(crawler.clone(), warc)
         .or(build_get_urls(crawler.clone()))
         .or(build_get_urls_count(crawler));
 
@@ -31,17 +36,26 @@ fn build_crawler_domains() -> impl Filter<Extract = (Domains,), Error = warp::Re
 
 /// Creates our filter system for POSTing the  `Url` at:
 /// `<HOST>/crawler/domains/`
+///
+/// Every crawl started through this filter shares one `Crawler`, so the host
+/// limits apply across all crawls at once, and every crawl is archived with
+/// `warc`, if given.
 fn build_post_domain(
     crawler: impl Filter<Extract = (Domains,), Error = warp::Rejection> + Clone,
+    warc: Option<WarcWriter>,
 ) -> impl Filter<Extract = (CrawlDomain,), Error = warp::Rejection> + Clone {
-    let client = Client::new();
-    let client = warp::any().map(move || client.clone());
+    let mut builder = CrawlerBuilder::new().host_limits(DEFAULT_HOST_LIMITS);
+    if let Some(warc) = warc {
+        builder = builder.warc(warc);
+    }
+    let url_crawler = builder.build().expect("Failed to create HTTP client");
+    let url_crawler = warp::any().map(move || url_crawler.clone());
     crawler
         .and(warp::post())
         .and(warp::path::end())
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(client)
+        .and(url_crawler)
         .and_then(add_domain)
 }
 
@@ -85,3 +99,9 @@ fn get_command_line_socket_addr() -> SocketAddr {
             8080,
         ))
 }
+
+/// Grab the directory to archive crawls to as WARC files from the second
+/// command line argument, if given.
+fn get_command_line_warc_dir() -> Option<PathBuf> {
+    env::args().nth(2).map(PathBuf::from)
+}
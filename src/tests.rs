@@ -7,7 +7,7 @@ use super::*;
 #[tokio::test]
 async fn test_post() {
     let crawler = build_crawler_domains();
-    let post_domain = build_post_domain(crawler);
+    let post_domain = build_post_domain(crawler, None);
 
     let url = "simple.test";
 
@@ -28,7 +28,7 @@ async fn test_post() {
 #[tokio::test]
 async fn test_invalid_scheme_post() {
     let crawler = build_crawler_domains();
-    let post_domain = build_post_domain(crawler);
+    let post_domain = build_post_domain(crawler, None);
 
     let url = "ftp://simple.test";
 
@@ -45,7 +45,7 @@ async fn test_invalid_scheme_post() {
 #[tokio::test]
 async fn test_duplicate_post() {
     let crawler = build_crawler_domains();
-    let post_domain = build_post_domain(crawler);
+    let post_domain = build_post_domain(crawler, None);
 
     let build_post_test = |url| {
         warp::test::request()
@@ -63,7 +63,7 @@ async fn test_duplicate_post() {
 #[tokio::test]
 async fn test_get_urls_good_domain() {
     let crawler = build_crawler_domains();
-    let post_domain = build_post_domain(crawler.clone());
+    let post_domain = build_post_domain(crawler.clone(), None);
     let get_urls_domain = build_get_urls(crawler);
 
     let url = "some.test";
@@ -100,7 +100,7 @@ async fn test_get_urls_bad_domain() {
 #[tokio::test]
 async fn test_get_urls_count_good_domain() {
     let crawler = build_crawler_domains();
-    let post_domain = build_post_domain(crawler.clone());
+    let post_domain = build_post_domain(crawler.clone(), None);
     let get_urls_count_domain = build_get_urls_count(crawler);
 
     let url = "some.test";
@@ -140,7 +140,7 @@ async fn test_get_urls_count_bad_domain() {
 #[tokio::test]
 async fn test_get_urls_no_access() {
     let crawler = build_crawler_domains();
-    let post_domain = build_post_domain(crawler.clone());
+    let post_domain = build_post_domain(crawler.clone(), None);
     let get_urls_count_domain = build_get_urls_count(crawler);
 
     let url = "some.invalid";
@@ -0,0 +1,246 @@
+//! Canonicalization of URLs, so that URLs for the same resource are only
+//! crawled once.
+use reqwest::Url;
+
+/// A single step of URL canonicalization.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Canonicalize {
+    /// Removes the fragment, so `page.html#content` becomes `page.html`.
+    StripFragment,
+    /// Decodes percent-encoded unreserved characters, and upper-cases the hex
+    /// digits of those that remain, so `%7euser%2f` becomes `~user%2F`.
+    NormalizePercentEncoding,
+    /// Sorts query parameters by name, keeping the order of repeated names,
+    /// and removes an empty query.
+    SortQuery,
+    /// Removes the query parameters with any of the given names, such as
+    /// tracking parameters.
+    RemoveQueryParams(Vec<String>),
+    /// Removes a trailing `/` from any path other than `/`.
+    StripTrailingSlash,
+}
+
+impl Canonicalize {
+    /// Applies this step to `url`.
+    fn apply(&self, url: &mut Url) {
+        match self {
+            Canonicalize::StripFragment => url.set_fragment(None),
+            Canonicalize::NormalizePercentEncoding => {
+                let path = normalize_percent_encoding(url.path());
+                url.set_path(&path);
+                if let Some(query) = url.query().map(normalize_percent_encoding) {
+                    url.set_query(Some(&query));
+                }
+            }
+            Canonicalize::SortQuery => {
+                let mut pairs: Vec<&str> = query_pairs(url);
+                pairs.sort_by_key(|pair| pair.split('=').next());
+                set_query_pairs(url, &pairs.join("&"));
+            }
+            Canonicalize::RemoveQueryParams(names) => {
+                let pairs: Vec<&str> = query_pairs(url)
+                    .into_iter()
+                    .filter(|pair| {
+                        let name = pair.split('=').next().unwrap_or_default();
+                        !names.iter().any(|n| n == name)
+                    })
+                    .collect();
+                set_query_pairs(url, &pairs.join("&"));
+            }
+            Canonicalize::StripTrailingSlash => {
+                let path = url.path();
+                if path.len() > 1 && path.ends_with('/') {
+                    let path = path[..path.len() - 1].to_owned();
+                    url.set_path(&path);
+                }
+            }
+        }
+    }
+}
+
+/// An ordered list of canonicalization steps, applied to every URL a crawl
+/// comes across before it is checked against the URLs already visited.
+///
+/// Parsing a URL already lower-cases its scheme and host, and removes any
+/// port that is the default for its scheme, so those steps are implied.
+///
+/// The default strips fragments and normalizes percent-encoding, neither of
+/// which changes the resource a URL refers to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Canonicalizer {
+    /// The steps applied, in order.
+    steps: Vec<Canonicalize>,
+}
+
+impl Default for Canonicalizer {
+    fn default() -> Self {
+        Self::new(vec![
+            Canonicalize::StripFragment,
+            Canonicalize::NormalizePercentEncoding,
+        ])
+    }
+}
+
+impl Canonicalizer {
+    /// Creates a canonicalizer applying `steps` in order.
+    pub fn new(steps: Vec<Canonicalize>) -> Self {
+        Self { steps }
+    }
+
+    /// Creates a canonicalizer that leaves URLs as they are.
+    pub fn none() -> Self {
+        Self::new(Vec::new())
+    }
+
+    /// The steps applied, in order.
+    pub fn steps(&self) -> &[Canonicalize] {
+        &self.steps
+    }
+
+    /// Returns the canonical form of `url`.
+    pub fn canonicalize(&self, url: &Url) -> Url {
+        let mut url = url.clone();
+        for step in &self.steps {
+            step.apply(&mut url);
+        }
+        url
+    }
+}
+
+/// Returns the `&`-separated parts of the query of `url`, still encoded,
+/// without any empty parts.
+fn query_pairs(url: &Url) -> Vec<&str> {
+    url.query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .collect()
+}
+
+/// Sets the query of `url` to `query`, removing it entirely if empty.
+fn set_query_pairs(url: &mut Url, query: &str) {
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        url.set_query(Some(query));
+    }
+}
+
+/// Returns `s` with percent-encoded unreserved characters decoded, and the
+/// hex digits of every other percent-encoded byte upper-cased.
+fn normalize_percent_encoding(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = match bytes.get(i..i + 3) {
+            Some([b'%', hi, lo]) => hex_value(*hi).zip(hex_value(*lo)).map(|(h, l)| h * 16 + l),
+            _ => None,
+        };
+        match decoded {
+            Some(byte) if is_unreserved(byte) => {
+                out.push(byte as char);
+                i += 3;
+            }
+            Some(_) => {
+                out.push('%');
+                out.push(bytes[i + 1].to_ascii_uppercase() as char);
+                out.push(bytes[i + 2].to_ascii_uppercase() as char);
+                i += 3;
+            }
+            None => {
+                // `s` is a valid URL component, so it is ASCII and each byte
+                // is a character.
+                out.push(bytes[i] as char);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Returns the value of the hex digit `c`, if it is one.
+fn hex_value(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+/// Returns `true` for the characters RFC 3986 says need never be encoded.
+fn is_unreserved(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'-' || c == b'.' || c == b'_' || c == b'~'
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn canonical(steps: Vec<Canonicalize>, url: &str) -> String {
+        let url = Url::parse(url).unwrap();
+        Canonicalizer::new(steps).canonicalize(&url).to_string()
+    }
+
+    #[test]
+    fn test_default() {
+        let canonicalizer = Canonicalizer::default();
+        let a = Url::parse("https://www.enhance.com:443/teams.php#content").unwrap();
+        let b = Url::parse("HTTPS://WWW.ENHANCE.COM/teams.php").unwrap();
+        assert_eq!(
+            canonicalizer.canonicalize(&a),
+            canonicalizer.canonicalize(&b)
+        );
+    }
+
+    #[test]
+    fn test_percent_encoding() {
+        assert_eq!(
+            canonical(
+                vec![Canonicalize::NormalizePercentEncoding],
+                "https://www.enhance.com/%7euser/a%2fb?q=%e2%82%ac%41"
+            ),
+            "https://www.enhance.com/~user/a%2Fb?q=%E2%82%ACA"
+        );
+    }
+
+    #[test]
+    fn test_sort_query() {
+        assert_eq!(
+            canonical(
+                vec![Canonicalize::SortQuery],
+                "https://www.enhance.com/?b=2&a=1&b=1&"
+            ),
+            "https://www.enhance.com/?a=1&b=2&b=1"
+        );
+        assert_eq!(
+            canonical(vec![Canonicalize::SortQuery], "https://www.enhance.com/?"),
+            "https://www.enhance.com/"
+        );
+    }
+
+    #[test]
+    fn test_remove_query_params() {
+        let step = Canonicalize::RemoveQueryParams(vec!["utm_source".to_owned()]);
+        assert_eq!(
+            canonical(
+                vec![step.clone()],
+                "https://www.enhance.com/?utm_source=x&id=1"
+            ),
+            "https://www.enhance.com/?id=1"
+        );
+        assert_eq!(
+            canonical(vec![step], "https://www.enhance.com/?utm_source=x"),
+            "https://www.enhance.com/"
+        );
+    }
+
+    #[test]
+    fn test_strip_trailing_slash() {
+        let step = Canonicalize::StripTrailingSlash;
+        assert_eq!(
+            canonical(vec![step.clone()], "https://www.enhance.com/docs/"),
+            "https://www.enhance.com/docs"
+        );
+        assert_eq!(
+            canonical(vec![step], "https://www.enhance.com/"),
+            "https://www.enhance.com/"
+        );
+    }
+}
@@ -0,0 +1,350 @@
+//! Detection of the character encoding of HTML documents and stylesheets,
+//! following the encoding sniffing algorithms of the HTML and CSS standards.
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+
+/// How many bytes of a document are searched for a `<meta>` declaration.
+const PRESCAN_LENGTH: usize = 1024;
+
+/// Decodes the HTML document `body`, served with the `Content-Type`
+/// `content_type`, to text.
+///
+/// The encoding is taken from the first of a byte order mark, the `charset`
+/// parameter of `content_type`, and a `<meta>` declaration near the start of
+/// the document. Documents declaring none of these are decoded as UTF-8 if
+/// they are valid UTF-8, and otherwise as windows-1252, the default the HTML
+/// standard suggests for most locales. Invalid sequences are replaced with
+/// U+FFFD.
+pub(crate) fn decode(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = Encoding::for_bom(body)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset_param))
+        .or_else(|| prescan(body))
+        .unwrap_or_else(|| match std::str::from_utf8(body) {
+            Ok(_) => UTF_8,
+            Err(_) => WINDOWS_1252,
+        });
+    // `decode` removes any byte order mark.
+    encoding.decode(body).0.into_owned()
+}
+
+/// Decodes the stylesheet `body`, served with the `Content-Type`
+/// `content_type`, to text.
+///
+/// The encoding is taken from the first of a byte order mark, the `charset`
+/// parameter of `content_type`, and an `@charset` rule at the very start of
+/// the stylesheet. Stylesheets declaring none of these are decoded as UTF-8.
+pub(crate) fn decode_css(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = Encoding::for_bom(body)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset_param))
+        .or_else(|| charset_rule(body))
+        .unwrap_or(UTF_8);
+    encoding.decode(body).0.into_owned()
+}
+
+/// Returns the encoding named by the `@charset "...";` rule `body` starts
+/// with, if any.
+fn charset_rule(body: &[u8]) -> Option<&'static Encoding> {
+    let rest = body.strip_prefix(b"@charset \"")?;
+    let end = find(rest, b"\";")?;
+    // A declaration read as ASCII cannot really be in UTF-16.
+    Some(match Encoding::for_label(&rest[..end])? {
+        e if e == UTF_16LE || e == UTF_16BE => UTF_8,
+        e => e,
+    })
+}
+
+/// Returns the encoding named by the `charset` parameter in `value`, the
+/// value of a `Content-Type` header or `<meta>` `content` attribute.
+fn charset_param(value: &str) -> Option<&'static Encoding> {
+    let lower = value.to_ascii_lowercase();
+    let mut rest = lower.as_str();
+    loop {
+        let start = rest.find("charset")?;
+        rest = rest[start + "charset".len()..].trim_start();
+        if let Some(value) = rest.strip_prefix('=') {
+            rest = value.trim_start();
+            break;
+        }
+    }
+    let label = match rest.chars().next()? {
+        quote @ ('"' | '\'') => rest[1..].split(quote).next()?,
+        _ => rest
+            .split(|c: char| c == ';' || c.is_ascii_whitespace())
+            .next()?,
+    };
+    Encoding::for_label(label.as_bytes())
+}
+
+/// Searches the start of `body` for a `<meta charset>` or
+/// `<meta http-equiv="content-type">` declaration, following the prescan of
+/// the HTML standard. Comments, the attributes of other tags, and other markup
+/// declarations are skipped rather than searched.
+fn prescan(body: &[u8]) -> Option<&'static Encoding> {
+    let bytes = &body[..body.len().min(PRESCAN_LENGTH)];
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        if rest.starts_with(b"<!--") {
+            // The dashes of `<!--` may also close it, as in `<!-->`.
+            i += 2 + find(&rest[2..], b"-->")? + 2;
+        } else if is_meta_tag(rest) {
+            i += 5;
+            if let Some(encoding) = meta_encoding(bytes, &mut i) {
+                // A declaration read as ASCII cannot really be in UTF-16.
+                return Some(match encoding {
+                    e if e == UTF_16LE || e == UTF_16BE => UTF_8,
+                    e if e == X_USER_DEFINED => WINDOWS_1252,
+                    e => e,
+                });
+            }
+        } else if is_tag(rest) {
+            i += rest
+                .iter()
+                .position(|&b| b.is_ascii_whitespace() || b == b'>')?;
+            while attribute(bytes, &mut i).is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            i += find(rest, b">")?;
+        }
+        // Every branch stops at the last byte it read, usually a `>`.
+        i += 1;
+    }
+    None
+}
+
+/// Returns `true` if `bytes` starts with a `<meta` tag.
+fn is_meta_tag(bytes: &[u8]) -> bool {
+    bytes.len() > 5
+        && bytes[..5].eq_ignore_ascii_case(b"<meta")
+        && (bytes[5].is_ascii_whitespace() || bytes[5] == b'/')
+}
+
+/// Returns `true` if `bytes` starts with a start or end tag.
+fn is_tag(bytes: &[u8]) -> bool {
+    match bytes {
+        [b'<', b'/', c, ..] | [b'<', c, ..] => c.is_ascii_alphabetic(),
+        _ => false,
+    }
+}
+
+/// Reads the attributes of a `<meta>` tag from position `i` of `bytes`,
+/// leaving `i` at the end of the tag, and returns the encoding they declare,
+/// if any.
+///
+/// Only the first attribute of each name counts, and a charset in the
+/// `content` attribute counts only alongside `http-equiv="content-type"`.
+fn meta_encoding(bytes: &[u8], i: &mut usize) -> Option<&'static Encoding> {
+    let mut names = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+    while let Some((name, value)) = attribute(bytes, i) {
+        if names.contains(&name) {
+            continue;
+        }
+        match name.as_str() {
+            "http-equiv" => got_pragma |= value == "content-type",
+            "content" if charset.is_none() => {
+                if let Some(encoding) = charset_param(&value) {
+                    charset = Some(encoding);
+                    need_pragma = Some(true);
+                }
+            }
+            "charset" => {
+                if let Some(encoding) = Encoding::for_label(value.as_bytes()) {
+                    charset = Some(encoding);
+                    need_pragma = Some(false);
+                }
+            }
+            _ => {}
+        }
+        names.push(name);
+    }
+    match need_pragma? {
+        true if !got_pragma => None,
+        _ => charset,
+    }
+}
+
+/// Reads the attribute at position `i` of `bytes`, leaving `i` after it.
+/// Returns its lower-cased name and value, or `None` at the `>` closing the
+/// tag or the end of `bytes`.
+fn attribute(bytes: &[u8], i: &mut usize) -> Option<(String, String)> {
+    while bytes.get(*i)?.is_ascii_whitespace() || bytes[*i] == b'/' {
+        *i += 1;
+    }
+    if bytes[*i] == b'>' {
+        return None;
+    }
+    let lossy = |bytes: Vec<u8>| String::from_utf8_lossy(&bytes).into_owned();
+    let mut name = Vec::new();
+    loop {
+        match *bytes.get(*i)? {
+            // An attribute name may start with `=`.
+            b'=' if !name.is_empty() => break,
+            b'/' | b'>' => return Some((lossy(name), String::new())),
+            b if b.is_ascii_whitespace() => {
+                while bytes.get(*i)?.is_ascii_whitespace() {
+                    *i += 1;
+                }
+                if bytes[*i] != b'=' {
+                    return Some((lossy(name), String::new()));
+                }
+                break;
+            }
+            b => name.push(b.to_ascii_lowercase()),
+        }
+        *i += 1;
+    }
+    // Skip the `=`, and any spaces after it.
+    *i += 1;
+    while bytes.get(*i)?.is_ascii_whitespace() {
+        *i += 1;
+    }
+    let mut value = Vec::new();
+    match bytes[*i] {
+        quote @ (b'"' | b'\'') => loop {
+            *i += 1;
+            match *bytes.get(*i)? {
+                b if b == quote => {
+                    *i += 1;
+                    break;
+                }
+                b => value.push(b.to_ascii_lowercase()),
+            }
+        },
+        b'>' => {}
+        _ => {
+            while let Some(&b) = bytes.get(*i) {
+                if b.is_ascii_whitespace() || b == b'>' {
+                    break;
+                }
+                value.push(b.to_ascii_lowercase());
+                *i += 1;
+            }
+        }
+    }
+    Some((lossy(name), lossy(value)))
+}
+
+/// Returns the position of `needle` in `haystack`, if it is there.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_windows_1252() {
+        assert_eq!(decode(b"caf\xe9", None), "caf\u{e9}");
+        assert_eq!(
+            decode(b"caf\xc3\xa9\xe9", Some("text/html")),
+            "caf\u{c3}\u{a9}\u{e9}"
+        );
+    }
+
+    #[test]
+    fn test_default_utf8() {
+        assert_eq!(
+            decode("caf\u{e9}".as_bytes(), Some("text/html")),
+            "caf\u{e9}"
+        );
+    }
+
+    #[test]
+    fn test_bom() {
+        // The byte order mark wins over the header, and is removed.
+        let body = b"\xef\xbb\xbfcaf\xc3\xa9";
+        assert_eq!(
+            decode(body, Some("text/html; charset=windows-1252")),
+            "caf\u{e9}"
+        );
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(
+            decode(b"caf\xe9", Some("text/html; charset=ISO-8859-1")),
+            "caf\u{e9}"
+        );
+        assert_eq!(
+            decode(b"caf\xe9", Some("text/html;charset=\"windows-1252\"")),
+            "caf\u{e9}"
+        );
+        // The header wins over the document.
+        let body = b"<meta charset=\"shift_jis\">caf\xe9";
+        assert!(decode(body, Some("text/html; charset=windows-1252")).ends_with("caf\u{e9}"));
+    }
+
+    #[test]
+    fn test_meta_charset() {
+        let body = b"<!DOCTYPE html><html><head><META CHARSET=Shift_JIS></head>\x93\xfa\x96\x7b";
+        assert!(decode(body, Some("text/html")).ends_with("\u{65e5}\u{672c}"));
+    }
+
+    #[test]
+    fn test_meta_http_equiv() {
+        let body =
+            b"<meta http-equiv='Content-Type' content='text/html; charset=windows-1252'>caf\xe9";
+        assert!(decode(body, None).ends_with("caf\u{e9}"));
+    }
+
+    #[test]
+    fn test_prescan_skips_comments() {
+        let body = b"<!-- <meta charset=shift_jis> --><meta charset=windows-1252>caf\xe9";
+        assert!(decode(body, None).ends_with("caf\u{e9}"));
+        assert_eq!(prescan(b"<!--><meta charset=utf-8>"), Some(UTF_8));
+        assert_eq!(prescan(b"<!-- --!><meta charset=utf-8> -->"), None);
+    }
+
+    #[test]
+    fn test_prescan_skips_tags() {
+        let body = b"<img alt='<meta charset=shift_jis>'><meta charset=utf-8>";
+        assert_eq!(prescan(body), Some(UTF_8));
+        let body = b"<?xml version='1.0'?><!DOCTYPE html></p ><meta charset=utf-8>";
+        assert_eq!(prescan(body), Some(UTF_8));
+    }
+
+    #[test]
+    fn test_meta_pragma() {
+        // A charset in `content` needs `http-equiv`, but one in `charset`
+        // does not.
+        assert_eq!(prescan(b"<meta content='text/html; charset=utf-8'>"), None);
+        let body = b"<meta charset=utf-8 http-equiv=refresh>";
+        assert_eq!(prescan(body), Some(UTF_8));
+        // Only the first attribute of a name counts.
+        let body = b"<meta charset=utf-8 charset=shift_jis>";
+        assert_eq!(prescan(body), Some(UTF_8));
+    }
+
+    #[test]
+    fn test_css() {
+        assert_eq!(
+            decode_css(
+                b"@charset \"iso-8859-1\"; a::after { content: \"caf\xe9\" }",
+                None
+            ),
+            "@charset \"iso-8859-1\"; a::after { content: \"caf\u{e9}\" }"
+        );
+        // The rule must be exactly at the start, in its exact form.
+        assert_eq!(
+            decode_css(b" @charset \"iso-8859-1\"; \xe9", None),
+            " @charset \"iso-8859-1\"; \u{fffd}"
+        );
+        assert_eq!(
+            decode_css(b"caf\xe9", Some("text/css; charset=windows-1252")),
+            "caf\u{e9}"
+        );
+    }
+
+    #[test]
+    fn test_meta_utf16() {
+        assert_eq!(prescan(b"<meta charset=utf-16le>"), Some(UTF_8));
+        assert_eq!(
+            prescan(b"<meta charset=x-user-defined>"),
+            Some(WINDOWS_1252)
+        );
+    }
+}
@@ -0,0 +1,568 @@
+//! Configuration for a crawl, and the builder used to produce a `Crawler`.
+use crate::{
+    Canonicalizer, Crawler, Fetcher, HostLimits, LinkSource, RateLimitedFetcher, RateLimiter,
+    UrlPattern, WarcWriter, DEFAULT_CONCURRENCY, DEFAULT_MAX_REDIRECTS, DEFAULT_ROBOTS_AGENT,
+};
+use reqwest::redirect::Policy;
+use reqwest::{Client, ClientBuilder, Result, Url};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A predicate over discovered URLs. Only URLs for which every filter returns
+/// `true` are crawled.
+pub type UrlFilter = Arc<dyn Fn(&Url) -> bool + Send + Sync>;
+
+/// Which discovered URLs are considered part of a crawl, relative to the URL
+/// the crawl started from.
+///
+/// Every scope covers both http and https URLs, apart from `Origin`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Scope {
+    /// URLs with exactly the same host as the starting URL.
+    #[default]
+    Host,
+    /// URLs with the same scheme, host and port as the starting URL.
+    Origin,
+    /// URLs with the same host as the starting URL, or a subdomain of it. A
+    /// crawl started at `example.com` includes `docs.example.com`, but one
+    /// started at `www.example.com` does not include `example.com`.
+    HostAndSubdomains,
+    /// URLs whose host has the same registrable domain as the starting URL,
+    /// according to the public suffix list. So `www.example.com`,
+    /// `example.com` and `docs.example.com` are one site, while
+    /// `a.github.io` and `b.github.io` are not. Hosts that are IP addresses
+    /// must match exactly.
+    RegistrableDomain,
+    /// URLs whose host is one of the listed hosts, whatever the starting URL.
+    /// Hosts are compared case-insensitively.
+    Hosts(Vec<String>),
+}
+
+impl Scope {
+    /// Returns `true` if `url` falls within this scope for a crawl started at
+    /// `seed`.
+    pub fn contains(&self, seed: &Url, url: &Url) -> bool {
+        match self {
+            Scope::Host => seed.host_str() == url.host_str(),
+            Scope::Origin => seed.origin() == url.origin(),
+            Scope::HostAndSubdomains => match (seed.host_str(), url.host_str()) {
+                (Some(seed), Some(host)) => {
+                    host == seed
+                        || host
+                            .strip_suffix(seed)
+                            .is_some_and(|sub| sub.ends_with('.'))
+                }
+                _ => false,
+            },
+            Scope::RegistrableDomain => match (seed.domain(), url.domain()) {
+                (Some(seed), Some(host)) => registrable_domain(seed) == registrable_domain(host),
+                _ => seed.host_str() == url.host_str(),
+            },
+            Scope::Hosts(hosts) => url
+                .host_str()
+                .is_some_and(|host| hosts.iter().any(|h| h.eq_ignore_ascii_case(host))),
+        }
+    }
+}
+
+/// Returns the registrable domain of `host`, or `host` itself if it has none,
+/// such as when it is a public suffix.
+fn registrable_domain(host: &str) -> &str {
+    let host = host.trim_end_matches('.');
+    psl::domain_str(host).unwrap_or(host)
+}
+
+/// The policy a crawl runs under.
+///
+/// The default configuration matches the behaviour of `crawl_domain`.
+#[derive(Clone)]
+pub struct CrawlConfig {
+    /// The maximum number of page requests in flight at once.
+    pub concurrency: usize,
+    /// The maximum number of pages to request before the crawl ends, if any.
+    pub max_pages: Option<usize>,
+    /// The maximum number of body bytes to download before the crawl ends, if
+    /// any. Requests already in flight when it is reached still complete.
+    pub max_bytes: Option<u64>,
+    /// The maximum time to crawl for, if any. Requests still in flight when
+    /// it runs out are abandoned.
+    pub max_duration: Option<Duration>,
+    /// The maximum number of links followed from the starting URL, if any.
+    /// Pages at this depth are visited, but their links are not followed.
+    pub max_depth: Option<usize>,
+    /// Which discovered URLs are followed.
+    pub scope: Scope,
+    /// Rewrites every URL found to its canonical form before it is checked
+    /// against the URLs already visited.
+    pub canonicalizer: Canonicalizer,
+    /// The `User-Agent` header sent with every request, if any. When `None`
+    /// the client's own user agent is used.
+    pub user_agent: Option<String>,
+    /// The time allowed for each request to complete, if any.
+    pub timeout: Option<Duration>,
+    /// The largest page body read, in bytes, if any. Larger pages fail with
+    /// `Error::TooLarge`.
+    pub max_body_size: Option<u64>,
+    /// Whether `nofollow` is obeyed, from `rel="nofollow"` links,
+    /// `<meta name="robots">` elements and `X-Robots-Tag` headers.
+    pub respect_nofollow: bool,
+    /// The elements and attributes links are extracted from. Defaults to
+    /// `LinkSource::DEFAULT`. Stylesheets are read for links only when this
+    /// includes a CSS source, such as `LinkSource::CssUrl`.
+    pub link_sources: Vec<LinkSource>,
+    /// Whether each page is requested with HEAD before GET, so that resources
+    /// that are not HTML, other than stylesheets being read for links, are
+    /// never downloaded. Without this, their responses are dropped once the
+    /// `Content-Type` arrives, without reading the body.
+    pub head_first: bool,
+    /// The most redirects followed for a single page. Pages that redirect
+    /// more often fail with `Error::TooManyRedirects`.
+    pub max_redirects: usize,
+    /// Patterns, one of which in-scope URLs must match to be followed. When
+    /// empty, every in-scope URL is included. The starting URL is always
+    /// crawled.
+    pub include: Vec<UrlPattern>,
+    /// Patterns that stop in-scope URLs being followed if any match, even if
+    /// they are included.
+    pub exclude: Vec<UrlPattern>,
+    /// Additional predicates that in-scope URLs must satisfy to be followed.
+    pub filters: Vec<UrlFilter>,
+    /// Whether each origin's `robots.txt` is fetched and obeyed, including
+    /// its `Crawl-delay`.
+    pub respect_robots_txt: bool,
+    /// The product token matched against `robots.txt` `User-agent` lines.
+    pub robots_agent: String,
+    /// Whether each origin's sitemaps are read to find pages to visit. The
+    /// sitemaps listed in `robots.txt` are used, along with `/sitemap.xml`.
+    /// Sitemaps listed by a sitemap index are only read if they are in scope.
+    ///
+    /// Sitemaps are requested like pages: within `concurrency` and the
+    /// budgets, and only where `robots.txt` allows when it is respected. At
+    /// most 1000 sitemaps are read by each crawl.
+    pub sitemaps: bool,
+    /// Archives every request made for a page, `robots.txt` file or sitemap,
+    /// and its response, if any. Pages whose exchanges cannot be archived
+    /// fail with `Error::Archive`. A `robots.txt` file that cannot be
+    /// archived still applies, and the failure is reported with a `Failed`
+    /// result for the file's URL. Sitemaps are read whether or not they can
+    /// be archived.
+    pub warc: Option<WarcWriter>,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+            max_pages: None,
+            max_bytes: None,
+            max_duration: None,
+            max_depth: None,
+            scope: Scope::default(),
+            canonicalizer: Canonicalizer::default(),
+            user_agent: None,
+            timeout: None,
+            max_body_size: None,
+            respect_nofollow: false,
+            link_sources: LinkSource::DEFAULT.to_vec(),
+            head_first: false,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            filters: Vec::new(),
+            respect_robots_txt: true,
+            robots_agent: DEFAULT_ROBOTS_AGENT.to_owned(),
+            sitemaps: false,
+            warc: None,
+        }
+    }
+}
+
+impl CrawlConfig {
+    /// Returns `true` if `url`, discovered during a crawl started at `seed`,
+    /// should be followed.
+    pub fn should_follow(&self, seed: &Url, url: &Url) -> bool {
+        (url.scheme() == "http" || url.scheme() == "https")
+            && self.scope.contains(seed, url)
+            && (self.include.is_empty() || self.include.iter().any(|p| p.matches(url)))
+            && !self.exclude.iter().any(|p| p.matches(url))
+            && self.filters.iter().all(|f| f(url))
+    }
+}
+
+/// Builder for a `Crawler`.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use web_crawler_lib::{
+///     Canonicalize, Canonicalizer, CrawlerBuilder, HostLimits, Scope, UrlPattern,
+/// };
+/// use reqwest::{Result, Url};
+/// use futures::stream::StreamExt;
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<()> {
+///     let crawler = CrawlerBuilder::new()
+///         .concurrency(16)
+///         .max_pages(500)
+///         .max_bytes(100 * 1024 * 1024)
+///         .max_duration(Duration::from_secs(15 * 60))
+///         .max_depth(3)
+///         .scope(Scope::Origin)
+///         .canonicalizer(Canonicalizer::new(vec![
+///             Canonicalize::StripFragment,
+///             Canonicalize::SortQuery,
+///             Canonicalize::RemoveQueryParams(vec!["utm_source".to_owned()]),
+///         ]))
+///         .user_agent("my-crawler/1.0")
+///         .robots_agent("my-crawler")
+///         .timeout(Duration::from_secs(10))
+///         .include(UrlPattern::glob("/docs/**"))
+///         .exclude(UrlPattern::glob("/docs/logout"))
+///         .exclude(UrlPattern::regex("[?&]sort=").unwrap())
+///         .host_limits(HostLimits {
+///             requests_per_second: Some(4.0),
+///             max_connections: Some(2),
+///             ..HostLimits::default()
+///         })
+///         .build()?;
+///
+///     let url = Url::parse("https://www.enhance.com/").unwrap();
+///     let mut stream = Box::pin(crawler.crawl(url));
+///     while let Some(value) = stream.next().await {
+///         println!("Got {}", value.url);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct CrawlerBuilder {
+    /// The configuration handed to the built `Crawler`.
+    config: CrawlConfig,
+    /// What to make requests with, if the caller supplied it.
+    fetcher: Option<Arc<dyn Fetcher>>,
+    /// The connect timeout used when building our own client.
+    connect_timeout: Option<Duration>,
+    /// The limiter the fetcher is wrapped with, if set.
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl CrawlerBuilder {
+    /// Creates a builder with the default `CrawlConfig`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a builder starting from an existing `config`.
+    pub fn from_config(config: CrawlConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the maximum number of page requests in flight at once. A value of
+    /// `0` is treated as `1`.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.config.concurrency = concurrency;
+        self
+    }
+
+    /// Ends the crawl once `max_pages` pages have been requested.
+    pub fn max_pages(mut self, max_pages: usize) -> Self {
+        self.config.max_pages = Some(max_pages);
+        self
+    }
+
+    /// Ends the crawl once `max_bytes` bytes of bodies have been downloaded.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.config.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Ends the crawl once it has run for `max_duration`.
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.config.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Stops following links more than `max_depth` clicks from the starting
+    /// URL.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.config.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Sets which discovered URLs are followed.
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.config.scope = scope;
+        self
+    }
+
+    /// Sets how URLs are canonicalized before they are de-duplicated.
+    pub fn canonicalizer(mut self, canonicalizer: Canonicalizer) -> Self {
+        self.config.canonicalizer = canonicalizer;
+        self
+    }
+
+    /// Sets the `User-Agent` header sent with every request.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.config.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Sets the time allowed for each request to complete.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// Fails pages whose bodies are larger than `max_body_size` bytes.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.config.max_body_size = Some(max_body_size);
+        self
+    }
+
+    /// Sets whether `nofollow` is obeyed, from `rel="nofollow"` links,
+    /// `<meta name="robots">` elements and `X-Robots-Tag` headers.
+    pub fn respect_nofollow(mut self, respect_nofollow: bool) -> Self {
+        self.config.respect_nofollow = respect_nofollow;
+        self
+    }
+
+    /// Sets the elements and attributes links are extracted from, such as
+    /// `LinkSource::ALL`.
+    pub fn link_sources(mut self, link_sources: &[LinkSource]) -> Self {
+        self.config.link_sources = link_sources.to_vec();
+        self
+    }
+
+    /// Sets whether each page is requested with HEAD before GET.
+    pub fn head_first(mut self, head_first: bool) -> Self {
+        self.config.head_first = head_first;
+        self
+    }
+
+    /// Sets the most redirects followed for a single page.
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.config.max_redirects = max_redirects;
+        self
+    }
+
+    /// Sets the time allowed to establish a connection.
+    ///
+    /// This only applies when the builder creates its own client, and is
+    /// ignored if one is given with `client` or `fetcher`.
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// Adds a pattern to those in-scope URLs must match one of to be
+    /// followed.
+    pub fn include(mut self, pattern: UrlPattern) -> Self {
+        self.config.include.push(pattern);
+        self
+    }
+
+    /// Adds a pattern that stops in-scope URLs it matches being followed.
+    pub fn exclude(mut self, pattern: UrlPattern) -> Self {
+        self.config.exclude.push(pattern);
+        self
+    }
+
+    /// Adds a predicate that in-scope URLs must satisfy to be followed.
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&Url) -> bool + Send + Sync + 'static,
+    {
+        self.config.filters.push(Arc::new(filter));
+        self
+    }
+
+    /// Sets whether each origin's `robots.txt` is fetched and obeyed.
+    pub fn respect_robots_txt(mut self, respect_robots_txt: bool) -> Self {
+        self.config.respect_robots_txt = respect_robots_txt;
+        self
+    }
+
+    /// Sets the product token matched against `robots.txt` `User-agent`
+    /// lines.
+    pub fn robots_agent(mut self, robots_agent: impl Into<String>) -> Self {
+        self.config.robots_agent = robots_agent.into();
+        self
+    }
+
+    /// Sets whether each origin's sitemaps are read to find pages to visit.
+    pub fn sitemaps(mut self, sitemaps: bool) -> Self {
+        self.config.sitemaps = sitemaps;
+        self
+    }
+
+    /// Limits how hard each host is hit, with a limiter of its own.
+    pub fn host_limits(mut self, limits: HostLimits) -> Self {
+        self.rate_limiter = Some(RateLimiter::new(limits));
+        self
+    }
+
+    /// Limits how hard each host is hit with `rate_limiter`, which may be
+    /// shared with other crawlers.
+    pub fn rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Archives every request made for a page, `robots.txt` file or sitemap,
+    /// and its response, with `writer`, which may be shared with other
+    /// crawlers.
+    pub fn warc(mut self, writer: WarcWriter) -> Self {
+        self.config.warc = Some(writer);
+        self
+    }
+
+    /// Crawls with `client` rather than a newly created client.
+    ///
+    /// A client that follows redirects itself hides the redirects from the
+    /// crawl, so only the final location of each is checked against the
+    /// crawl's scope. Build `client` with `redirect::Policy::none()` to have
+    /// every hop recorded and checked.
+    pub fn client(mut self, client: Client) -> Self {
+        self.fetcher = Some(Arc::new(client));
+        self
+    }
+
+    /// Makes requests with `fetcher` rather than a newly created client, such
+    /// as a `MemoryFetcher` serving fixtures.
+    pub fn fetcher<F: Fetcher>(mut self, fetcher: F) -> Self {
+        self.fetcher = Some(Arc::new(fetcher));
+        self
+    }
+
+    /// Produces the `Crawler`, creating a client if neither a client nor a
+    /// fetcher was given.
+    ///
+    /// The fetcher is wrapped in a `RateLimitedFetcher` with the configured
+    /// limiter, if any. A given `RateLimitedFetcher` is otherwise used as it
+    /// is, and requests wait on both limiters if one is configured too.
+    pub fn build(self) -> Result<Crawler> {
+        let fetcher: Arc<dyn Fetcher> = match self.fetcher {
+            Some(fetcher) => fetcher,
+            None => {
+                // Redirects are followed by the crawl itself, one hop at a
+                // time.
+                let mut builder = ClientBuilder::new()
+                    .redirect(Policy::none())
+                    .http1_title_case_headers();
+                if let Some(connect_timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(connect_timeout);
+                }
+                Arc::new(builder.build()?)
+            }
+        };
+        let fetcher: Arc<dyn Fetcher> = match self.rate_limiter {
+            Some(limiter) => Arc::new(RateLimitedFetcher::from_shared(fetcher, limiter)),
+            None => fetcher,
+        };
+        Ok(Crawler::from_parts(fetcher, self.config))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_host_scope() {
+        let seed = Url::parse("https://www.enhance.com/").unwrap();
+        let http = Url::parse("http://www.enhance.com/a.html").unwrap();
+        let other = Url::parse("https://www.google.com/").unwrap();
+        assert!(Scope::Host.contains(&seed, &http));
+        assert!(!Scope::Host.contains(&seed, &other));
+    }
+
+    #[test]
+    fn test_origin_scope() {
+        let seed = Url::parse("https://www.enhance.com/").unwrap();
+        let https = Url::parse("https://www.enhance.com/a.html").unwrap();
+        let http = Url::parse("http://www.enhance.com/a.html").unwrap();
+        assert!(Scope::Origin.contains(&seed, &https));
+        assert!(!Scope::Origin.contains(&seed, &http));
+    }
+
+    #[test]
+    fn test_subdomain_scope() {
+        let seed = Url::parse("https://example.com/").unwrap();
+        let docs = Url::parse("http://docs.example.com/").unwrap();
+        let lookalike = Url::parse("https://badexample.com/").unwrap();
+        assert!(Scope::HostAndSubdomains.contains(&seed, &seed));
+        assert!(Scope::HostAndSubdomains.contains(&seed, &docs));
+        assert!(!Scope::HostAndSubdomains.contains(&seed, &lookalike));
+        assert!(!Scope::HostAndSubdomains.contains(&docs, &seed));
+    }
+
+    #[test]
+    fn test_registrable_domain_scope() {
+        let seed = Url::parse("https://www.example.co.uk/").unwrap();
+        let bare = Url::parse("https://example.co.uk/").unwrap();
+        let docs = Url::parse("https://docs.example.co.uk/").unwrap();
+        let other = Url::parse("https://other.co.uk/").unwrap();
+        assert!(Scope::RegistrableDomain.contains(&seed, &bare));
+        assert!(Scope::RegistrableDomain.contains(&seed, &docs));
+        assert!(!Scope::RegistrableDomain.contains(&seed, &other));
+
+        let a = Url::parse("https://a.github.io/").unwrap();
+        let b = Url::parse("https://b.github.io/").unwrap();
+        assert!(!Scope::RegistrableDomain.contains(&a, &b));
+
+        let ip = Url::parse("http://127.0.0.1/").unwrap();
+        assert!(Scope::RegistrableDomain.contains(&ip, &ip));
+        assert!(!Scope::RegistrableDomain.contains(&ip, &bare));
+    }
+
+    #[test]
+    fn test_hosts_scope() {
+        let seed = Url::parse("https://www.example.com/").unwrap();
+        let cdn = Url::parse("https://cdn.example.com/a.css").unwrap();
+        let scope = Scope::Hosts(vec![
+            "WWW.example.com".to_owned(),
+            "cdn.example.com".to_owned(),
+        ]);
+        assert!(scope.contains(&seed, &seed));
+        assert!(scope.contains(&seed, &cdn));
+        assert!(!scope.contains(&seed, &Url::parse("https://example.com/").unwrap()));
+    }
+
+    #[test]
+    fn test_filters() {
+        let seed = Url::parse("https://www.enhance.com/").unwrap();
+        let logout = Url::parse("https://www.enhance.com/logout").unwrap();
+        let config = CrawlerBuilder::new()
+            .filter(|url| url.path() != "/logout")
+            .config;
+        assert!(config.should_follow(&seed, &seed));
+        assert!(!config.should_follow(&seed, &logout));
+    }
+
+    #[test]
+    fn test_include_exclude() {
+        let seed = Url::parse("https://www.enhance.com/docs/").unwrap();
+        let config = CrawlerBuilder::new()
+            .include(UrlPattern::glob("/docs/**"))
+            .exclude(UrlPattern::glob("**?sort=*"))
+            .config;
+        let page = Url::parse("https://www.enhance.com/docs/a.html").unwrap();
+        let sorted = Url::parse("https://www.enhance.com/docs/?sort=date").unwrap();
+        let blog = Url::parse("https://www.enhance.com/blog/").unwrap();
+        assert!(config.should_follow(&seed, &page));
+        assert!(!config.should_follow(&seed, &sorted));
+        assert!(!config.should_follow(&seed, &blog));
+    }
+
+    #[test]
+    fn test_scheme_not_followed() {
+        let seed = Url::parse("https://www.enhance.com/").unwrap();
+        let mail = Url::parse("mailto:someone@www.enhance.com").unwrap();
+        assert!(!CrawlConfig::default().should_follow(&seed, &mail));
+    }
+}
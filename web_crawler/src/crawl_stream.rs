@@ -0,0 +1,60 @@
+//! The stream of results produced by a crawl.
+use crate::CrawlResult;
+use futures::stream::{BoxStream, Stream};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// A limit on the resources a single crawl may use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Budget {
+    /// The maximum number of pages requested, `CrawlConfig::max_pages`.
+    Pages,
+    /// The maximum number of bytes downloaded, `CrawlConfig::max_bytes`.
+    Bytes,
+    /// The maximum time spent crawling, `CrawlConfig::max_duration`.
+    Duration,
+}
+
+/// Where a crawl records the budget that ended it, shared between the crawl
+/// and its `CrawlStream`.
+pub(crate) type BudgetReport = Arc<Mutex<Option<Budget>>>;
+
+/// A Stream of `CrawlResult`s, which can report why it ended.
+///
+/// The stream is `Unpin`, so it may be polled without pinning it first.
+pub struct CrawlStream<T = ()> {
+    /// The results of the crawl.
+    inner: BoxStream<'static, CrawlResult<T>>,
+    /// The budget that ended the crawl, once one has.
+    exhausted: BudgetReport,
+}
+
+impl<T> CrawlStream<T> {
+    /// Wraps the results of a crawl, `inner`, which records the budget that
+    /// ended it in `exhausted`.
+    pub(crate) fn new(inner: BoxStream<'static, CrawlResult<T>>, exhausted: BudgetReport) -> Self {
+        Self { inner, exhausted }
+    }
+
+    /// Returns the budget that ended the crawl early, if any.
+    ///
+    /// Once the stream has ended, `None` means every URL in scope was
+    /// crawled. While the stream is running, a budget may already have been
+    /// hit while the last requests in flight finish.
+    pub fn budget_exhausted(&self) -> Option<Budget> {
+        *self.exhausted.lock().unwrap()
+    }
+}
+
+impl<T> Stream for CrawlStream<T> {
+    type Item = CrawlResult<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<CrawlResult<T>>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
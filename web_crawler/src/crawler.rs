@@ -0,0 +1,147 @@
+//! The configured entry point for running crawls.
+use crate::{
+    archive, broken_links, canonical_clusters, request, BrokenLink, CrawlConfig, CrawlStream,
+    CrawlStreamState, Error, Extractor, Fetcher, LinkGraph, RateLimitedFetcher, RateLimiter,
+    ResponseInfo,
+};
+use futures::stream::{self, StreamExt};
+use reqwest::{Client, Method, Url};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use std::vec;
+
+/// Runs crawls under a fixed `CrawlConfig`, sharing a single `Fetcher` across
+/// all of them.
+///
+/// The fetcher is a `RateLimitedFetcher`, so that every crawl obeys the
+/// `Crawl-delay` of each host. Fetchers that are not are wrapped in one
+/// applying `DEFAULT_HOST_LIMITS`.
+///
+/// A `Crawler` is cheap to clone. Create one with `CrawlerBuilder`.
+#[derive(Clone)]
+pub struct Crawler {
+    /// What makes crawl requests.
+    fetcher: Arc<dyn Fetcher>,
+    /// The policy every crawl runs under.
+    config: Arc<CrawlConfig>,
+}
+
+impl Crawler {
+    /// Creates a `Crawler` that crawls with `client` under `config`.
+    pub fn new(client: Client, config: CrawlConfig) -> Self {
+        Self::with_fetcher(client, config)
+    }
+
+    /// Creates a `Crawler` that makes its requests with `fetcher`, under
+    /// `config`.
+    pub fn with_fetcher<F: Fetcher>(fetcher: F, config: CrawlConfig) -> Self {
+        Self::from_parts(Arc::new(fetcher), config)
+    }
+
+    /// Creates a `Crawler` sharing `fetcher`, under `config`.
+    pub(crate) fn from_parts(fetcher: Arc<dyn Fetcher>, config: CrawlConfig) -> Self {
+        let fetcher: Arc<dyn Fetcher> = match fetcher.rate_limiter() {
+            Some(_) => fetcher,
+            None => Arc::new(RateLimitedFetcher::from_shared(
+                fetcher,
+                RateLimiter::default(),
+            )),
+        };
+        Self {
+            fetcher,
+            config: Arc::new(config),
+        }
+    }
+
+    /// The fetcher this crawler makes requests with.
+    pub fn fetcher(&self) -> &dyn Fetcher {
+        self.fetcher.as_ref()
+    }
+
+    /// The policy this crawler's crawls run under.
+    pub fn config(&self) -> &CrawlConfig {
+        &self.config
+    }
+
+    /// Sends a HEAD request for `url`, as a crawl would, such as to check the
+    /// host can be reached before crawling it. The request is subject to the
+    /// fetcher's host limits, and archived if a WARC writer is configured.
+    pub async fn probe(&self, url: Url) -> Result<ResponseInfo, Error> {
+        let head = request(&self.config, Method::HEAD, url);
+        let start = Instant::now();
+        let sent = SystemTime::now();
+        let response = self.fetcher.fetch(head.clone()).await?;
+        let info = ResponseInfo::new(&response, start.elapsed());
+        archive(&self.config, &head, sent, &info, &[], None).await?;
+        Ok(info)
+    }
+
+    /// Returns a Stream that runs over all URLs reachable from `url` within
+    /// the configured scope. See `crawl_domain`.
+    ///
+    /// The stream ends early if any configured budget is used up, which
+    /// `CrawlStream::budget_exhausted` then reports.
+    pub fn crawl(&self, url: Url) -> CrawlStream {
+        self.crawl_with(url, ())
+    }
+
+    /// Alternative to `crawl` that attaches the data `extractor` extracts
+    /// from each HTML page to its result.
+    pub fn crawl_with<E: Extractor>(&self, url: Url, extractor: E) -> CrawlStream<E::Output> {
+        let init_state = CrawlStreamState::create(
+            self.fetcher.clone(),
+            self.config.clone(),
+            Arc::new(extractor),
+            url,
+        );
+        let exhausted = init_state.exhausted.clone();
+        // From our initial state attempt to generate a stream.
+        let results = stream::unfold(init_state, |state| state.pop_document());
+        CrawlStream::new(results.boxed(), exhausted)
+    }
+
+    /// Returns a complete list of all URLs visited from `url`. See
+    /// `unique_url_list`.
+    pub async fn unique_url_list(&self, url: Url) -> vec::Vec<Url> {
+        self.crawl(url)
+            .filter(|r| futures::future::ready(r.is_visited()))
+            .map(|r| r.url)
+            .collect()
+            .await
+    }
+
+    /// Returns a complete count of all URLs visited from `url`. See
+    /// `unique_url_count`.
+    pub async fn unique_url_count(&self, url: Url) -> usize {
+        self.crawl(url)
+            .filter(|r| futures::future::ready(r.is_visited()))
+            .fold(0, |i, _| futures::future::ready(i + 1))
+            .await
+    }
+
+    /// Returns the pages visited from `url`, grouped by the canonical URL
+    /// they declared. See `canonical_clusters`.
+    pub async fn canonical_clusters(&self, url: Url) -> BTreeMap<Url, Vec<Url>> {
+        let results: Vec<_> = self.crawl(url).collect().await;
+        canonical_clusters(&results)
+    }
+
+    /// Returns the graph of the links between the pages visited from `url`.
+    pub async fn link_graph(&self, url: Url) -> LinkGraph {
+        let canonicalizer = &self.config.canonicalizer;
+        self.crawl(url)
+            .fold(LinkGraph::new(), |mut graph, result| {
+                graph.add_result(&result, canonicalizer);
+                futures::future::ready(graph)
+            })
+            .await
+    }
+
+    /// Returns every URL visited from `url` that failed, with the pages that
+    /// link to it. See `broken_links`.
+    pub async fn broken_links(&self, url: Url) -> Vec<BrokenLink> {
+        let results: Vec<_> = self.crawl(url).collect().await;
+        broken_links(&results, &self.config.canonicalizer)
+    }
+}
@@ -0,0 +1,207 @@
+//! Extraction of the links in CSS, from `url()` references and `@import`
+//! rules.
+use crate::{Link, LinkSource};
+use reqwest::Url;
+
+/// Returns the links in the stylesheet `css`, found in any of `sources`, in
+/// the order they appear. Relative links are resolved against `base`, and
+/// links that are not valid URLs are ignored.
+pub(crate) fn css_links(css: &str, base: &Url, sources: &[LinkSource]) -> Vec<Link> {
+    references(css)
+        .into_iter()
+        .filter(|(source, _)| sources.contains(source))
+        .filter_map(|(source, raw_url)| {
+            let raw_url = raw_url.trim();
+            if raw_url.is_empty() {
+                return None;
+            }
+            Some(Link {
+                url: base.join(raw_url).ok()?,
+                source,
+                nofollow: false,
+                text: None,
+            })
+        })
+        .collect()
+}
+
+/// Returns the URLs referenced by `css`, and whether each was referenced by
+/// an `@import` rule or a `url()`. Comments, and strings outside of either,
+/// are skipped.
+fn references(css: &str) -> Vec<(LinkSource, String)> {
+    let mut references = Vec::new();
+    let mut i = 0;
+    while i < css.len() {
+        let rest = &css[i..];
+        if rest.starts_with("/*") {
+            i += rest.find("*/").map_or(rest.len(), |end| end + 2);
+        } else if rest.starts_with(['"', '\'']) {
+            i += string(rest).1;
+        } else if starts_with_ignore_case(rest, "@import") {
+            i += "@import".len();
+            let rest = &css[i..];
+            let token = rest.trim_start();
+            let parsed = if token.starts_with(['"', '\'']) {
+                Some(string(token))
+            } else if starts_with_ignore_case(token, "url(") {
+                Some(url_token(token))
+            } else {
+                None
+            };
+            if let Some((url, len)) = parsed {
+                references.push((LinkSource::CssImport, url));
+                i += rest.len() - token.len() + len;
+            }
+        } else if starts_with_ignore_case(rest, "url(") && !follows_name(css, i) {
+            let (url, len) = url_token(rest);
+            references.push((LinkSource::CssUrl, url));
+            i += len;
+        } else {
+            // Checked by the loop condition: there is a character left.
+            i += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+    references
+}
+
+/// Reads the quoted string `css` starts with. Returns its unescaped value and
+/// the number of bytes read. A string ends at its closing quote, or at an
+/// unescaped line break.
+fn string(css: &str) -> (String, usize) {
+    let mut chars = css.char_indices();
+    // Checked by the caller: `css` starts with a quote.
+    let (_, quote) = chars.next().unwrap();
+    let mut value = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return (value, i + 1),
+            '\n' => return (value, i),
+            '\\' => match chars.next() {
+                // An escaped line break continues the string.
+                Some((_, '\n')) => {}
+                Some((_, c)) => value.push(c),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    (value, css.len())
+}
+
+/// Reads the `url()` `css` starts with. Returns the URL it holds and the
+/// number of bytes read, up to and including the closing `)`.
+fn url_token(css: &str) -> (String, usize) {
+    let start = "url(".len();
+    let rest = &css[start..];
+    let arg = rest.trim_start();
+    let mut i = start + rest.len() - arg.len();
+    let value = if arg.starts_with(['"', '\'']) {
+        let (value, len) = string(arg);
+        i += len;
+        value
+    } else {
+        let mut value = String::new();
+        let mut chars = arg.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                ')' => break,
+                '\\' => {
+                    if let Some(c) = chars.next() {
+                        value.push(c);
+                        i += c.len_utf8();
+                    }
+                }
+                c => value.push(c),
+            }
+            i += c.len_utf8();
+        }
+        value
+    };
+    // Skip anything left before the closing `)`.
+    let end = css[i..].find(')').map_or(css.len(), |end| i + end + 1);
+    (value, end)
+}
+
+/// Returns `true` if `s` starts with `prefix`, ignoring ASCII case.
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    s.get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
+
+/// Returns `true` if the byte at `i` in `css` continues a name, such as the
+/// `url(` in `my-url(`, rather than starting a token.
+fn follows_name(css: &str, i: usize) -> bool {
+    css[..i]
+        .chars()
+        .next_back()
+        .is_some_and(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '\\')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const STYLESHEET: &str = r#"@import "reset.css";
+        @IMPORT url('/fonts/family.css') screen;
+        /* background: url(commented.png); */
+        @font-face { src: url( "fonts/font.woff2" ) format("woff2"); }
+        a::before { content: "url(not-a-link.png)"; }
+        body { background: URL(bg.png) no-repeat, my-url(nothing.png); }
+        .escaped { background: url(a\(1\).png); }
+        .empty { background: url(); }"#;
+
+    fn links(sources: &[LinkSource]) -> Vec<(String, LinkSource)> {
+        let base = Url::parse("https://www.enhance.com/css/site.css").unwrap();
+        css_links(STYLESHEET, &base, sources)
+            .into_iter()
+            .map(|link| (link.url.to_string(), link.source))
+            .collect()
+    }
+
+    #[test]
+    fn test_css_links() {
+        assert_eq!(
+            links(&[LinkSource::CssImport, LinkSource::CssUrl]),
+            vec![
+                (
+                    "https://www.enhance.com/css/reset.css".to_owned(),
+                    LinkSource::CssImport
+                ),
+                (
+                    "https://www.enhance.com/fonts/family.css".to_owned(),
+                    LinkSource::CssImport
+                ),
+                (
+                    "https://www.enhance.com/css/fonts/font.woff2".to_owned(),
+                    LinkSource::CssUrl
+                ),
+                (
+                    "https://www.enhance.com/css/bg.png".to_owned(),
+                    LinkSource::CssUrl
+                ),
+                (
+                    "https://www.enhance.com/css/a(1).png".to_owned(),
+                    LinkSource::CssUrl
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_css_sources() {
+        let imports = links(&[LinkSource::CssImport]);
+        assert_eq!(imports.len(), 2);
+        assert!(imports
+            .iter()
+            .all(|(_, source)| *source == LinkSource::CssImport));
+    }
+
+    #[test]
+    fn test_unterminated() {
+        assert_eq!(
+            references("a { background: url('open.png"),
+            vec![(LinkSource::CssUrl, "open.png".to_owned())]
+        );
+        assert_eq!(references("@import"), vec![]);
+    }
+}
@@ -0,0 +1,157 @@
+//! Page-level indexing directives, from `<meta name="robots">` elements and
+//! `X-Robots-Tag` headers.
+use reqwest::header::HeaderMap;
+use scraper::html::Html;
+use scraper::selector::Selector;
+
+/// The name of the header carrying indexing directives.
+const X_ROBOTS_TAG: &str = "x-robots-tag";
+
+/// Directives that are not agent names, but may be followed by a `:`.
+const VALUED_DIRECTIVES: &[&str] = &[
+    "unavailable_after",
+    "max-snippet",
+    "max-image-preview",
+    "max-video-preview",
+];
+
+/// What a page asked of crawlers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct RobotsDirectives {
+    /// The page asked not to be indexed.
+    pub noindex: bool,
+    /// The page asked for none of its links to be followed.
+    pub nofollow: bool,
+}
+
+impl RobotsDirectives {
+    /// Reads the directives in the `X-Robots-Tag` headers of `headers` that
+    /// apply to the product token `agent`.
+    ///
+    /// Each header holds a comma separated list of directives, which apply to
+    /// every crawler unless prefixed with an agent name and `:`.
+    pub fn from_headers(headers: &HeaderMap, agent: &str) -> Self {
+        let mut directives = Self::default();
+        for value in headers.get_all(X_ROBOTS_TAG) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            let value = match value.split_once(':') {
+                Some((name, rest)) if is_agent_name(name) => {
+                    if !name.trim().eq_ignore_ascii_case(agent) {
+                        continue;
+                    }
+                    rest
+                }
+                _ => value,
+            };
+            directives.add(value);
+        }
+        directives
+    }
+
+    /// Adds the directives of the `<meta name="robots">` elements of `html`,
+    /// and those named for the product token `agent`.
+    pub fn add_meta(&mut self, html: &Html, agent: &str) {
+        let selector = Selector::parse("meta[name][content]").unwrap();
+        for meta in html.select(&selector) {
+            let meta = meta.value();
+            // Checked by the selector: both attributes are present.
+            let name = meta.attr("name").unwrap().trim();
+            if name.eq_ignore_ascii_case("robots") || name.eq_ignore_ascii_case(agent) {
+                self.add(meta.attr("content").unwrap());
+            }
+        }
+    }
+
+    /// Adds the comma separated directives in `value`.
+    fn add(&mut self, value: &str) {
+        for directive in value.split(',') {
+            let directive = directive.trim();
+            if directive.eq_ignore_ascii_case("noindex") {
+                self.noindex = true;
+            } else if directive.eq_ignore_ascii_case("nofollow") {
+                self.nofollow = true;
+            } else if directive.eq_ignore_ascii_case("none") {
+                self.noindex = true;
+                self.nofollow = true;
+            }
+        }
+    }
+}
+
+/// Returns `true` if `prefix`, which came before a `:` in an `X-Robots-Tag`
+/// header, names an agent rather than being part of a directive.
+fn is_agent_name(prefix: &str) -> bool {
+    let prefix = prefix.trim();
+    !prefix.contains(',')
+        && !prefix.contains(char::is_whitespace)
+        && !VALUED_DIRECTIVES
+            .iter()
+            .any(|d| prefix.eq_ignore_ascii_case(d))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::DEFAULT_ROBOTS_AGENT;
+    use reqwest::header::HeaderValue;
+
+    fn headers(values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(X_ROBOTS_TAG, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_headers() {
+        let directives = RobotsDirectives::from_headers(
+            &headers(&["noindex", "unavailable_after: 25 Jun 2010 15:00:00 PST"]),
+            DEFAULT_ROBOTS_AGENT,
+        );
+        assert_eq!(
+            directives,
+            RobotsDirectives {
+                noindex: true,
+                nofollow: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_header_agents() {
+        let headers = headers(&["otherbot: noindex", "Web_Crawler_Lib: nofollow"]);
+        let directives = RobotsDirectives::from_headers(&headers, DEFAULT_ROBOTS_AGENT);
+        assert_eq!(
+            directives,
+            RobotsDirectives {
+                noindex: false,
+                nofollow: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_meta() {
+        let html = Html::parse_document(
+            r#"<meta name="ROBOTS" content="noindex"><meta name="otherbot" content="nofollow">"#,
+        );
+        let mut directives = RobotsDirectives::default();
+        directives.add_meta(&html, DEFAULT_ROBOTS_AGENT);
+        assert_eq!(
+            directives,
+            RobotsDirectives {
+                noindex: true,
+                nofollow: false
+            }
+        );
+
+        let html = Html::parse_document(r#"<meta name="web_crawler_lib" content="none">"#);
+        let mut directives = RobotsDirectives::default();
+        directives.add_meta(&html, DEFAULT_ROBOTS_AGENT);
+        assert!(directives.noindex && directives.nofollow);
+    }
+}
@@ -0,0 +1,175 @@
+//! The ways visiting a single URL can fail.
+use reqwest::{StatusCode, Url};
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+/// Why a URL could not be crawled.
+///
+/// Errors carry the message of the underlying error rather than the error
+/// itself, so that they may be cloned and compared.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The host name could not be resolved.
+    Dns(String),
+    /// A connection to the host could not be made.
+    Connect(String),
+    /// The request or response took longer than the configured timeout.
+    Timeout,
+    /// A secure connection could not be established, such as when the
+    /// certificate is invalid.
+    Tls(String),
+    /// The server responded with an error status, 4xx or 5xx.
+    HttpStatus(StatusCode),
+    /// The body of the response could not be read or decoded.
+    BodyDecode(String),
+    /// The body of the response is larger than the configured maximum, in
+    /// bytes.
+    TooLarge(u64),
+    /// A redirect led back to the given URL, which had already been
+    /// requested while following redirects.
+    RedirectLoop(Url),
+    /// More redirects were followed than the configured maximum.
+    TooManyRedirects(usize),
+    /// The request failed for some other reason.
+    Request(String),
+    /// The exchange could not be written to the configured WARC archive.
+    Archive(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Dns(e) => write!(f, "DNS lookup failed: {}", e),
+            Error::Connect(e) => write!(f, "connection failed: {}", e),
+            Error::Timeout => write!(f, "request timed out"),
+            Error::Tls(e) => write!(f, "TLS handshake failed: {}", e),
+            Error::HttpStatus(status) => write!(f, "HTTP status {}", status),
+            Error::BodyDecode(e) => write!(f, "could not read body: {}", e),
+            Error::TooLarge(max) => write!(f, "body larger than {} bytes", max),
+            Error::RedirectLoop(url) => write!(f, "redirect loop at {}", url),
+            Error::TooManyRedirects(max) => write!(f, "more than {} redirects", max),
+            Error::Request(e) => write!(f, "request failed: {}", e),
+            Error::Archive(e) => write!(f, "could not archive exchange: {}", e),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+impl From<reqwest::Error> for Error {
+    /// Classifies a `reqwest` error by what it reports of itself, and the
+    /// types of the errors it was caused by.
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return Error::Timeout;
+        }
+        let message = error_chain(&e);
+        if e.is_body() || e.is_decode() {
+            return Error::BodyDecode(message);
+        }
+        if e.is_connect() {
+            return connect_error(&e, message);
+        }
+        Error::Request(message)
+    }
+}
+
+/// Classifies a failure to connect by the first error caused by `e` that is
+/// of a known type.
+fn connect_error(e: &dyn StdError, message: String) -> Error {
+    let mut source = e.source();
+    while let Some(e) = source {
+        if e.is::<native_tls::Error>() {
+            return Error::Tls(message);
+        }
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            // A failed socket call carries the OS error code, while a failed
+            // lookup through `getaddrinfo` only has a message.
+            return match e.kind() {
+                io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::AddrNotAvailable
+                | io::ErrorKind::TimedOut => Error::Connect(message),
+                _ if e.raw_os_error().is_some() => Error::Connect(message),
+                _ => Error::Dns(message),
+            };
+        }
+        source = e.source();
+    }
+    Error::Connect(message)
+}
+
+/// Returns the message of `e` followed by those of the errors that caused it.
+fn error_chain(e: &dyn StdError) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    /// A failure to connect, caused by an I/O error.
+    #[derive(Debug)]
+    struct ConnectFailed(io::Error);
+
+    impl fmt::Display for ConnectFailed {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "error trying to connect")
+        }
+    }
+
+    impl StdError for ConnectFailed {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn test_dns() {
+        // A failed lookup through `getaddrinfo` only has a message.
+        let lookup = io::Error::other("failed to lookup address");
+        let e = ConnectFailed(lookup);
+        assert!(matches!(connect_error(&e, error_chain(&e)), Error::Dns(_)));
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        let e = ConnectFailed(refused);
+        assert!(matches!(
+            connect_error(&e, error_chain(&e)),
+            Error::Connect(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_connect() {
+        // Nothing listens on port 1 of the loopback address.
+        let e = reqwest::get("http://127.0.0.1:1/").await.unwrap_err();
+        assert!(matches!(Error::from(e), Error::Connect(_)));
+    }
+
+    #[tokio::test]
+    async fn test_tls() {
+        // A server that answers the handshake in plain HTTP.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n")
+                .unwrap();
+        });
+        let e = reqwest::get(&format!("https://{}/", addr))
+            .await
+            .unwrap_err();
+        assert!(matches!(Error::from(e), Error::Tls(_)));
+    }
+}
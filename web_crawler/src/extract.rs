@@ -0,0 +1,219 @@
+//! Extraction of data from the pages of a crawl, to be attached to their
+//! results.
+use crate::links::collapse_whitespace;
+use crate::ResponseInfo;
+use reqwest::Url;
+use scraper::html::Html;
+use scraper::selector::Selector;
+
+/// Extracts data from each HTML page a crawl visits. The data is attached to
+/// the page's `CrawlResult` as `data`, so that it need not be fetched again.
+///
+/// Extractors run as each page arrives, before its links are followed.
+/// Tuples of extractors extract the data of each of them. Pages are parsed
+/// with the `scraper` crate, which is re-exported as
+/// `web_crawler_lib::scraper` so that extractors use the same version.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use web_crawler_lib::{CrawlerBuilder, Extractor, ResponseInfo, Title};
+/// use futures::stream::StreamExt;
+/// use reqwest::Url;
+/// use web_crawler_lib::scraper::{Html, Selector};
+///
+/// /// Counts the images on each page.
+/// struct ImageCount;
+///
+/// impl Extractor for ImageCount {
+///     type Output = usize;
+///
+///     fn extract(&self, _url: &Url, html: &Html, _response: &ResponseInfo) -> usize {
+///         let selector = Selector::parse("img").unwrap();
+///         html.select(&selector).count()
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() {
+///     let crawler = CrawlerBuilder::new().build().unwrap();
+///     let url = Url::parse("https://www.linuxmint.com/").unwrap();
+///     let mut stream = crawler.crawl_with(url, (Title, ImageCount));
+///     while let Some(result) = stream.next().await {
+///         if let Some((title, images)) = result.data {
+///             println!("{}: {:?} has {} images", result.url, title, images);
+///         }
+///     }
+/// }
+/// ```
+pub trait Extractor: Send + Sync + 'static {
+    /// The data extracted from each page.
+    type Output: Send + 'static;
+
+    /// Extracts data from the document `html`, crawled as `url`, which
+    /// arrived with `response`. `url` is in its canonical form; where the
+    /// document was served from, after any redirects, is on `response`.
+    fn extract(&self, url: &Url, html: &Html, response: &ResponseInfo) -> Self::Output;
+}
+
+/// Extracts nothing. This is the extractor of `Crawler::crawl`.
+impl Extractor for () {
+    type Output = ();
+
+    fn extract(&self, _url: &Url, _html: &Html, _response: &ResponseInfo) {}
+}
+
+/// Implements `Extractor` for tuples of extractors.
+macro_rules! tuple_extractor {
+    ($($name:ident),+) => {
+        impl<$($name: Extractor),+> Extractor for ($($name,)+) {
+            type Output = ($($name::Output,)+);
+
+            #[allow(non_snake_case)]
+            fn extract(&self, url: &Url, html: &Html, response: &ResponseInfo) -> Self::Output {
+                let ($($name,)+) = self;
+                ($($name.extract(url, html, response),)+)
+            }
+        }
+    };
+}
+
+tuple_extractor!(A);
+tuple_extractor!(A, B);
+tuple_extractor!(A, B, C);
+tuple_extractor!(A, B, C, D);
+
+/// Extracts the text of a page's `<title>`, with whitespace collapsed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Title;
+
+impl Extractor for Title {
+    type Output = Option<String>;
+
+    fn extract(&self, _url: &Url, html: &Html, _response: &ResponseInfo) -> Option<String> {
+        let selector = Selector::parse("title").unwrap();
+        collapse_whitespace(html.select(&selector).next()?.text())
+    }
+}
+
+/// Extracts the `content` of a page's `<meta name="description">`, with
+/// whitespace collapsed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MetaDescription;
+
+impl Extractor for MetaDescription {
+    type Output = Option<String>;
+
+    fn extract(&self, _url: &Url, html: &Html, _response: &ResponseInfo) -> Option<String> {
+        let selector = Selector::parse("meta[name][content]").unwrap();
+        let meta = html.select(&selector).find(|meta| {
+            // Checked by the selector: the attribute is present.
+            let name = meta.value().attr("name").unwrap();
+            name.trim().eq_ignore_ascii_case("description")
+        })?;
+        collapse_whitespace(meta.value().attr("content"))
+    }
+}
+
+/// A heading of a page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Heading {
+    /// The rank of the heading, from `1` for `<h1>` to `6` for `<h6>`.
+    pub level: u8,
+    /// The text of the heading, with whitespace collapsed.
+    pub text: String,
+}
+
+/// Extracts the headings of a page, `<h1>` to `<h6>`, in document order.
+/// Headings without text are left out.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Headings;
+
+impl Extractor for Headings {
+    type Output = Vec<Heading>;
+
+    fn extract(&self, _url: &Url, html: &Html, _response: &ResponseInfo) -> Vec<Heading> {
+        let selector = Selector::parse("h1, h2, h3, h4, h5, h6").unwrap();
+        html.select(&selector)
+            .filter_map(|heading| {
+                // Checked by the selector: the name is `h` and a digit.
+                let level = heading.value().name()[1..].parse().unwrap();
+                let text = collapse_whitespace(heading.text())?;
+                Some(Heading { level, text })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use reqwest::header::HeaderMap;
+    use reqwest::{StatusCode, Version};
+    use std::time::Duration;
+
+    const PAGE: &str = r#"<html><head>
+        <title>
+            Enhance | Home
+        </title>
+        <meta name="Description" content="Tools for  better sites.">
+        </head><body>
+        <h1>Welcome</h1>
+        <h2>Our <em>tools</em></h2>
+        <h3></h3>
+        <h6>Small print</h6>
+        </body></html>"#;
+
+    fn extract<E: Extractor>(extractor: E, page: &str) -> E::Output {
+        let url = Url::parse("https://www.enhance.com/").unwrap();
+        let response = ResponseInfo {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            final_url: url.clone(),
+            redirects: Vec::new(),
+            content_type: Some("text/html".to_owned()),
+            content_length: Some(page.len() as u64),
+            headers: HeaderMap::new(),
+            elapsed: Duration::from_millis(1),
+        };
+        extractor.extract(&url, &Html::parse_document(page), &response)
+    }
+
+    #[test]
+    fn test_title() {
+        assert_eq!(extract(Title, PAGE), Some("Enhance | Home".to_owned()));
+        assert_eq!(extract(Title, "<p>No title</p>"), None);
+    }
+
+    #[test]
+    fn test_meta_description() {
+        assert_eq!(
+            extract(MetaDescription, PAGE),
+            Some("Tools for better sites.".to_owned())
+        );
+        assert_eq!(extract(MetaDescription, "<title>None</title>"), None);
+    }
+
+    #[test]
+    fn test_headings() {
+        let heading = |level, text: &str| Heading {
+            level,
+            text: text.to_owned(),
+        };
+        assert_eq!(
+            extract(Headings, PAGE),
+            vec![
+                heading(1, "Welcome"),
+                heading(2, "Our tools"),
+                heading(6, "Small print")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tuple() {
+        let (title, (), headings) = extract((Title, (), Headings), PAGE);
+        assert_eq!(title.as_deref(), Some("Enhance | Home"));
+        assert_eq!(headings.len(), 3);
+    }
+}
@@ -0,0 +1,309 @@
+//! The requests a crawl makes, and what makes them.
+use crate::{Error, RateLimiter};
+use futures::future::{self, BoxFuture};
+use futures::stream::{self, BoxStream, StreamExt};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, LOCATION};
+use reqwest::{Client, Method, StatusCode, Url, Version};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+/// A request for a `Fetcher` to make.
+#[derive(Clone, Debug)]
+pub struct FetchRequest {
+    /// The request method, `GET` or `HEAD`.
+    pub method: Method,
+    /// The URL to request.
+    pub url: Url,
+    /// Headers to send with the request, such as the configured user agent.
+    pub headers: HeaderMap,
+    /// How long the request may take, including reading the body, if
+    /// limited.
+    pub timeout: Option<Duration>,
+}
+
+impl FetchRequest {
+    /// Creates a `method` request for `url`, without headers or a timeout.
+    pub fn new(method: Method, url: Url) -> Self {
+        Self {
+            method,
+            url,
+            headers: HeaderMap::new(),
+            timeout: None,
+        }
+    }
+}
+
+/// The response to a `FetchRequest`, whose body has not yet been read.
+pub struct FetchResponse {
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The HTTP version the response was received over.
+    pub version: Version,
+    /// The URL the response came from. This differs from the requested URL
+    /// only when the fetcher follows redirects itself.
+    pub url: Url,
+    /// The headers of the response.
+    pub headers: HeaderMap,
+    /// The body of the response, in chunks as they arrive. A body that is
+    /// not needed is dropped without being read.
+    pub body: BoxStream<'static, Result<Vec<u8>, Error>>,
+}
+
+impl FetchResponse {
+    /// The length of the body in bytes, from the `Content-Length` header.
+    pub fn content_length(&self) -> Option<u64> {
+        self.headers
+            .get(CONTENT_LENGTH)?
+            .to_str()
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+}
+
+impl fmt::Debug for FetchResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FetchResponse")
+            .field("status", &self.status)
+            .field("version", &self.version)
+            .field("url", &self.url)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Makes the requests of a crawl, for its pages, `robots.txt` files and
+/// sitemaps.
+///
+/// A fetcher makes a single request for each `FetchRequest`. Redirects are
+/// left for the crawl to follow, so that each is recorded and checked
+/// against its scope. `reqwest::Client` is the fetcher crawls use unless
+/// another is given, while `MemoryFetcher` serves fixed responses.
+pub trait Fetcher: Send + Sync + 'static {
+    /// Makes `request`, resolving to its response once the status and
+    /// headers have arrived.
+    fn fetch(&self, request: FetchRequest) -> BoxFuture<'static, Result<FetchResponse, Error>>;
+
+    /// The limiter this fetcher's requests wait on, if any. Crawls give it
+    /// the `Crawl-delay` of each host's `robots.txt`.
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        None
+    }
+}
+
+/// Requests over HTTP. Build the client with `redirect::Policy::none()` to
+/// leave redirects to the crawl.
+impl Fetcher for Client {
+    fn fetch(&self, request: FetchRequest) -> BoxFuture<'static, Result<FetchResponse, Error>> {
+        let mut builder = self
+            .request(request.method, request.url)
+            .headers(request.headers);
+        if let Some(timeout) = request.timeout {
+            builder = builder.timeout(timeout);
+        }
+        Box::pin(async move {
+            let response = builder.send().await?;
+            let status = response.status();
+            let version = response.version();
+            let url = response.url().clone();
+            let headers = response.headers().clone();
+            // Chunks are read until the end of the body, or the first error.
+            let body = stream::unfold(Some(response), |response| async move {
+                let mut response = response?;
+                match response.chunk().await {
+                    Ok(Some(chunk)) => Some((Ok(chunk.to_vec()), Some(response))),
+                    Ok(None) => None,
+                    Err(e) => Some((Err(e.into()), None)),
+                }
+            });
+            Ok(FetchResponse {
+                status,
+                version,
+                url,
+                headers,
+                body: body.boxed(),
+            })
+        })
+    }
+}
+
+/// A response held by a `MemoryFetcher`.
+#[derive(Clone, Debug)]
+struct MemoryResponse {
+    /// The status of the response.
+    status: StatusCode,
+    /// The headers of the response.
+    headers: HeaderMap,
+    /// The body of the response.
+    body: Vec<u8>,
+}
+
+/// Serves fixed responses from memory, such as fixtures for tests.
+///
+/// URLs without a response are `404 Not Found`, so a site without a
+/// `robots.txt` allows everything. Requests for a URL may also be made to
+/// fail, as when its host cannot be reached.
+///
+/// ## Example
+///
+/// ```rust
+/// use web_crawler_lib::{Crawler, CrawlConfig, MemoryFetcher};
+/// use reqwest::Url;
+///
+/// #[tokio::main]
+/// pub async fn main() {
+///     let url = |path| Url::parse("https://www.enhance.com/").unwrap().join(path).unwrap();
+///     let fetcher = MemoryFetcher::new()
+///         .with_page(url("/"), "text/html", r#"<a href="/about.html">About</a>"#)
+///         .with_page(url("/about.html"), "text/html", "<h1>About</h1>");
+///     let crawler = Crawler::with_fetcher(fetcher, CrawlConfig::default());
+///     assert_eq!(crawler.unique_url_count(url("/")).await, 2);
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFetcher {
+    /// The response for each URL, or the error requests for it fail with.
+    responses: HashMap<Url, Result<MemoryResponse, Error>>,
+}
+
+impl MemoryFetcher {
+    /// Creates a fetcher without any responses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Responds to requests for `url` with `status`, `headers` and `body`. A
+    /// `Content-Length` header is added if `headers` has none.
+    pub fn with_response(
+        mut self,
+        url: Url,
+        status: StatusCode,
+        mut headers: HeaderMap,
+        body: impl Into<Vec<u8>>,
+    ) -> Self {
+        let body = body.into();
+        headers
+            .entry(CONTENT_LENGTH)
+            .or_insert_with(|| HeaderValue::from(body.len()));
+        let response = MemoryResponse {
+            status,
+            headers,
+            body,
+        };
+        self.responses.insert(url, Ok(response));
+        self
+    }
+
+    /// Fails requests for `url` with `error`.
+    pub fn with_error(mut self, url: Url, error: Error) -> Self {
+        self.responses.insert(url, Err(error));
+        self
+    }
+
+    /// Responds to requests for `url` with `200 OK`, the `Content-Type`
+    /// `content_type` and `body`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `content_type` is not a valid header value.
+    pub fn with_page(self, url: Url, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        let mut headers = HeaderMap::new();
+        let content_type = HeaderValue::from_str(content_type).expect("Invalid content type");
+        headers.insert(CONTENT_TYPE, content_type);
+        self.with_response(url, StatusCode::OK, headers, body)
+    }
+
+    /// Responds to requests for `from` with a `301 Moved Permanently`
+    /// redirect to `to`.
+    pub fn with_redirect(self, from: Url, to: &Url) -> Self {
+        let mut headers = HeaderMap::new();
+        // A URL is always a valid header value.
+        headers.insert(LOCATION, HeaderValue::from_str(to.as_str()).unwrap());
+        self.with_response(from, StatusCode::MOVED_PERMANENTLY, headers, Vec::new())
+    }
+}
+
+impl Fetcher for MemoryFetcher {
+    fn fetch(&self, request: FetchRequest) -> BoxFuture<'static, Result<FetchResponse, Error>> {
+        let response = match self.responses.get(&request.url).cloned() {
+            Some(Ok(response)) => response,
+            Some(Err(e)) => return Box::pin(future::ready(Err(e))),
+            None => MemoryResponse {
+                status: StatusCode::NOT_FOUND,
+                headers: HeaderMap::new(),
+                body: Vec::new(),
+            },
+        };
+        let body = match request.method {
+            Method::HEAD => stream::empty().boxed(),
+            _ => stream::once(future::ready(Ok(response.body))).boxed(),
+        };
+        Box::pin(future::ready(Ok(FetchResponse {
+            status: response.status,
+            version: Version::HTTP_11,
+            url: request.url,
+            headers: response.headers,
+            body,
+        })))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(method: Method, url: &Url) -> FetchRequest {
+        FetchRequest::new(method, url.clone())
+    }
+
+    async fn body(response: FetchResponse) -> Vec<u8> {
+        let chunks: Vec<_> = response.body.collect().await;
+        chunks.into_iter().flat_map(Result::unwrap).collect()
+    }
+
+    #[tokio::test]
+    async fn test_memory_fetcher() {
+        let url = Url::parse("https://www.enhance.com/").unwrap();
+        let fetcher = MemoryFetcher::new().with_page(url.clone(), "text/html", "<p>Hi</p>");
+
+        let response = fetcher.fetch(request(Method::GET, &url)).await.unwrap();
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.content_length(), Some(9));
+        assert_eq!(response.headers[CONTENT_TYPE], "text/html");
+        assert_eq!(body(response).await, b"<p>Hi</p>");
+
+        let response = fetcher.fetch(request(Method::HEAD, &url)).await.unwrap();
+        assert_eq!(response.content_length(), Some(9));
+        assert!(body(response).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_memory_fetcher_missing() {
+        let url = Url::parse("https://www.enhance.com/robots.txt").unwrap();
+        let fetcher = MemoryFetcher::new();
+        let response = fetcher.fetch(request(Method::GET, &url)).await.unwrap();
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_memory_fetcher_error() {
+        let url = Url::parse("https://foo.invalid/").unwrap();
+        let error = Error::Dns("failed to lookup address information".to_owned());
+        let fetcher = MemoryFetcher::new().with_error(url.clone(), error.clone());
+        let response = fetcher.fetch(request(Method::GET, &url)).await;
+        assert_eq!(response.err(), Some(error));
+    }
+
+    #[tokio::test]
+    async fn test_memory_fetcher_redirect() {
+        let from = Url::parse("https://www.enhance.com/old").unwrap();
+        let to = Url::parse("https://www.enhance.com/new").unwrap();
+        let fetcher = MemoryFetcher::new().with_redirect(from.clone(), &to);
+        let response = fetcher.fetch(request(Method::GET, &from)).await.unwrap();
+        assert_eq!(response.status, StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers[LOCATION], to.as_str());
+        assert_eq!(response.url, from);
+    }
+}
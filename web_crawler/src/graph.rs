@@ -0,0 +1,303 @@
+//! The directed graph of links between the pages of a crawl.
+use crate::{Canonicalizer, CrawlResult, LinkSource, Redirect};
+use reqwest::Url;
+use std::collections::{HashMap, HashSet};
+
+/// A URL in a `LinkGraph`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    /// The URL, in its canonical form.
+    pub url: Url,
+    /// Whether the URL was visited by the crawl. URLs that were only linked
+    /// to, such as those out of scope, were not.
+    pub visited: bool,
+}
+
+/// A link from one page to another in a `LinkGraph`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    /// The page the link was found on.
+    pub source: Url,
+    /// The URL linked to, in its canonical form.
+    pub target: Url,
+    /// The text of the link, if it has any. See `Link::text`.
+    pub text: Option<String>,
+    /// Where in the source page the link was found.
+    pub kind: LinkSource,
+}
+
+/// The directed graph of the links found during a crawl.
+///
+/// Every URL a crawl came across is a node, along with every URL linked to
+/// from a visited page. Each link found is an edge, so a page linking to
+/// another twice has two edges to it.
+///
+/// Redirects are kept apart from links: each redirect followed is in
+/// `redirects`, and the links on a page that was redirected to are found on
+/// the URL it was served from, which is visited along with the URL the crawl
+/// requested.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use web_crawler_lib::CrawlerBuilder;
+/// use reqwest::Url;
+///
+/// #[tokio::main]
+/// pub async fn main() {
+///     let crawler = CrawlerBuilder::new().build().unwrap();
+///     let url = Url::parse("https://www.linuxmint.com/").unwrap();
+///     let graph = crawler.link_graph(url).await;
+///     for node in graph.nodes() {
+///         println!("{} has {} inlinks", node.url, graph.inlinks(&node.url).count());
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LinkGraph {
+    /// Every node, in the order first seen.
+    nodes: Vec<Node>,
+    /// The position of each node in `nodes`, by URL.
+    index: HashMap<Url, usize>,
+    /// Every edge, in the order found.
+    edges: Vec<Edge>,
+    /// Every redirect, in the order followed.
+    redirects: Vec<Redirect>,
+}
+
+impl LinkGraph {
+    /// Creates an empty graph.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the graph of the links in `results`, whose link targets are
+    /// canonicalized with `canonicalizer` to match the URLs of results. This
+    /// should be the canonicalizer of the crawl.
+    pub fn from_results<'a, I, T>(results: I, canonicalizer: &Canonicalizer) -> Self
+    where
+        I: IntoIterator<Item = &'a CrawlResult<T>>,
+        T: 'a,
+    {
+        let mut graph = Self::new();
+        for result in results {
+            graph.add_result(result, canonicalizer);
+        }
+        graph
+    }
+
+    /// Adds the URL of `result`, any redirects followed from it, and its
+    /// links, to the graph. Redirect and link targets are canonicalized with
+    /// `canonicalizer`.
+    ///
+    /// This allows a graph to be built as a crawl's results arrive.
+    pub fn add_result<T>(&mut self, result: &CrawlResult<T>, canonicalizer: &Canonicalizer) {
+        let visited = result.is_visited();
+        let i = self.node(&result.url);
+        self.nodes[i].visited |= visited;
+        let mut source = result.url.clone();
+        if let Some(response) = &result.response {
+            for redirect in &response.redirects {
+                let redirect = Redirect {
+                    from: canonicalizer.canonicalize(&redirect.from),
+                    status: redirect.status,
+                    to: canonicalizer.canonicalize(&redirect.to),
+                };
+                self.node(&redirect.from);
+                self.node(&redirect.to);
+                self.redirects.push(redirect);
+            }
+            // The page, and so its links, came from where it redirected to.
+            source = canonicalizer.canonicalize(&response.final_url);
+            let i = self.node(&source);
+            self.nodes[i].visited |= visited;
+        }
+        for link in &result.links {
+            let target = canonicalizer.canonicalize(&link.url);
+            self.node(&target);
+            self.edges.push(Edge {
+                source: source.clone(),
+                target,
+                text: link.text.clone(),
+                kind: link.source,
+            });
+        }
+    }
+
+    /// Every URL in the graph, in the order first seen.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Every link in the graph, in the order found.
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Every redirect followed, in the order followed, with its URLs in
+    /// their canonical form.
+    pub fn redirects(&self) -> &[Redirect] {
+        &self.redirects
+    }
+
+    /// Returns the node for `url`, if it is in the graph.
+    pub fn get(&self, url: &Url) -> Option<&Node> {
+        self.index.get(url).map(|&i| &self.nodes[i])
+    }
+
+    /// Returns the links to `url`.
+    pub fn inlinks(&self, url: &Url) -> impl Iterator<Item = &Edge> {
+        let url = url.clone();
+        self.edges.iter().filter(move |edge| edge.target == url)
+    }
+
+    /// Returns the links found on `url`.
+    pub fn outlinks(&self, url: &Url) -> impl Iterator<Item = &Edge> {
+        let url = url.clone();
+        self.edges.iter().filter(move |edge| edge.source == url)
+    }
+
+    /// Returns the visited URLs no link or redirect in the graph leads to,
+    /// other than from the URL itself. These include the crawl's starting
+    /// URL, and pages found only through sitemaps.
+    pub fn orphans(&self) -> Vec<&Url> {
+        let edges = self.edges.iter().map(|edge| (&edge.source, &edge.target));
+        let redirects = self.redirects.iter().map(|r| (&r.from, &r.to));
+        let linked: HashSet<&Url> = edges
+            .chain(redirects)
+            .filter(|(source, target)| source != target)
+            .map(|(_, target)| target)
+            .collect();
+        self.nodes
+            .iter()
+            .filter(|node| node.visited && !linked.contains(&node.url))
+            .map(|node| &node.url)
+            .collect()
+    }
+
+    /// Returns the position of the node for `url`, adding it unvisited if it
+    /// is not yet in the graph.
+    fn node(&mut self, url: &Url) -> usize {
+        if let Some(&i) = self.index.get(url) {
+            return i;
+        }
+        self.nodes.push(Node {
+            url: url.clone(),
+            visited: false,
+        });
+        self.index.insert(url.clone(), self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{result, url};
+    use crate::ResponseInfo;
+    use reqwest::header::HeaderMap;
+    use reqwest::{StatusCode, Version};
+    use std::time::Duration;
+
+    fn graph() -> LinkGraph {
+        let results = vec![
+            result("/", &["/a.html#top", "/b.html", "/"]),
+            result("/a.html", &["/b.html"]),
+            result("/b.html", &[]),
+            result("/sitemap-only.html", &["/sitemap-only.html"]),
+        ];
+        LinkGraph::from_results(&results, &Canonicalizer::default())
+    }
+
+    #[test]
+    fn test_nodes() {
+        let graph = graph();
+        let urls: Vec<&str> = graph.nodes().iter().map(|n| n.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://www.enhance.com/",
+                "https://www.enhance.com/a.html",
+                "https://www.enhance.com/b.html",
+                "https://www.enhance.com/sitemap-only.html",
+            ]
+        );
+        assert!(graph.nodes().iter().all(|node| node.visited));
+        assert_eq!(graph.edges().len(), 5);
+    }
+
+    #[test]
+    fn test_links() {
+        let graph = graph();
+        let inlinks: Vec<&Url> = graph
+            .inlinks(&url("/b.html"))
+            .map(|edge| &edge.source)
+            .collect();
+        assert_eq!(inlinks, vec![&url("/"), &url("/a.html")]);
+        // Targets are canonicalized, but keep the text of the link.
+        let outlink = graph.outlinks(&url("/")).next().unwrap();
+        assert_eq!(outlink.target, url("/a.html"));
+        assert_eq!(outlink.text.as_deref(), Some("/a.html#top"));
+    }
+
+    #[test]
+    fn test_orphans() {
+        // Links to itself do not keep a page from being an orphan.
+        assert_eq!(
+            graph().orphans(),
+            vec![&url("/"), &url("/sitemap-only.html")]
+        );
+    }
+
+    #[test]
+    fn test_unvisited_targets() {
+        let results = vec![result("/", &["https://www.google.com/"])];
+        let graph = LinkGraph::from_results(&results, &Canonicalizer::default());
+        let google = Url::parse("https://www.google.com/").unwrap();
+        assert!(!graph.get(&google).unwrap().visited);
+        assert_eq!(graph.orphans(), vec![&url("/")]);
+    }
+
+    #[test]
+    fn test_redirects() {
+        let mut redirected = result("/old.html", &["b.html"]);
+        redirected.response = Some(ResponseInfo {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            final_url: url("/new/"),
+            redirects: vec![Redirect {
+                from: url("/old.html"),
+                status: StatusCode::MOVED_PERMANENTLY,
+                to: url("/new/#top"),
+            }],
+            content_type: Some("text/html".to_owned()),
+            content_length: None,
+            headers: HeaderMap::new(),
+            elapsed: Duration::from_millis(1),
+        });
+        redirected.links[0].url = url("/new/b.html");
+        let results = vec![result("/", &["/old.html"]), redirected];
+        let graph = LinkGraph::from_results(&results, &Canonicalizer::default());
+
+        assert_eq!(
+            graph.redirects(),
+            &[Redirect {
+                from: url("/old.html"),
+                status: StatusCode::MOVED_PERMANENTLY,
+                to: url("/new/"),
+            }]
+        );
+        // Both the requested URL and the one it redirected to were visited,
+        // and the links on the page are from where it was served.
+        assert!(graph.get(&url("/old.html")).unwrap().visited);
+        assert!(graph.get(&url("/new/")).unwrap().visited);
+        let outlinks: Vec<&Url> = graph
+            .outlinks(&url("/new/"))
+            .map(|edge| &edge.target)
+            .collect();
+        assert_eq!(outlinks, vec![&url("/new/b.html")]);
+        assert_eq!(graph.outlinks(&url("/old.html")).count(), 0);
+        // Being redirected to keeps a page from being an orphan.
+        assert_eq!(graph.orphans(), vec![&url("/")]);
+    }
+}
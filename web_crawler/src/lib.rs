@@ -1,19 +1,190 @@
+mod canonicalize;
+mod charset;
+mod config;
+mod crawl_stream;
+mod crawler;
+mod css;
+mod directives;
+mod error;
+mod extract;
+mod fetch;
+mod graph;
+mod links;
+mod pattern;
+mod rate_limit;
+mod report;
+mod response;
+mod robots;
+mod sitemap;
+mod test_util;
 mod tests;
+mod warc;
 
+pub use canonicalize::{Canonicalize, Canonicalizer};
+pub use config::{CrawlConfig, CrawlerBuilder, Scope, UrlFilter};
+use crawl_stream::BudgetReport;
+pub use crawl_stream::{Budget, CrawlStream};
+pub use crawler::Crawler;
+use css::css_links;
+use directives::RobotsDirectives;
+pub use error::Error;
+pub use extract::{Extractor, Heading, Headings, MetaDescription, Title};
+pub use fetch::{FetchRequest, FetchResponse, Fetcher, MemoryFetcher};
+pub use graph::{Edge, LinkGraph, Node};
+use links::{canonical_url, extract_links};
+pub use links::{Link, LinkSource};
+pub use pattern::UrlPattern;
+pub use rate_limit::{
+    HostLimits, HostPermit, RateLimitedFetcher, RateLimiter, DEFAULT_HOST_LIMITS, MAX_HOST_INTERVAL,
+};
+pub use report::{broken_links, BrokenLink, Referrer};
+use response::{is_css, is_html, read_body, read_body_prefix, redirect_location};
+pub use response::{Redirect, ResponseInfo};
+use robots::MAX_ROBOTS_SIZE;
+pub use robots::{Robots, DEFAULT_ROBOTS_AGENT};
+pub use sitemap::Sitemap;
+use sitemap::{MAX_SITEMAPS, MAX_SITEMAP_SIZE};
+pub use warc::{Truncated, WarcOptions, WarcWriter, DEFAULT_MAX_WARC_SIZE};
+// Extractors are given documents parsed by this version of `scraper`.
+pub use scraper;
+pub use scraper::Html;
+
+use futures::future::BoxFuture;
 use futures::stream;
-use reqwest::{Client, ClientBuilder, Result, Url};
-use scraper::html::Html;
-use scraper::selector::Selector;
-use std::collections::{BinaryHeap, HashSet};
-use std::str::FromStr;
+use futures::stream::FuturesUnordered;
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use reqwest::redirect::Policy;
+use reqwest::{ClientBuilder, Method, Result, Url};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use std::vec;
-use stream::Stream;
 use stream::StreamExt;
 
+/// The number of page requests a crawl keeps in flight at once, unless
+/// otherwise specified.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// The most redirects followed for a single page, unless otherwise specified.
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// The most redirects followed for a `robots.txt` file or sitemap, as RFC 9309
+/// asks of crawlers for `robots.txt`.
+const MAX_FILE_REDIRECTS: usize = 5;
+
 /// CrawlResult is output of a crawl.
-pub struct CrawlResult {
-    /// A given URL that was crawled.
+///
+/// `T` is the data an `Extractor` extracts from each page. See
+/// `Crawler::crawl_with`.
+pub struct CrawlResult<T = ()> {
+    /// A given URL that was crawled, in its canonical form.
     pub url: Url,
+    /// The URL as it was first found, before canonicalization. This is the
+    /// same as `url` when canonicalization left it unchanged.
+    pub original_url: Url,
+    /// The number of links followed from the starting URL to reach `url`.
+    /// The starting URL, and pages found through sitemaps, have depth `0`.
+    pub depth: usize,
+    /// The page `url` was first found on, if it was found by following a
+    /// link.
+    pub referrer: Option<Url>,
+    /// Whether the URL was visited, failed or was skipped.
+    pub outcome: CrawlOutcome,
+    /// The response received for the URL, if it was requested and a response
+    /// arrived. Failed URLs have a response when the failure was an error
+    /// status, or came while reading the body.
+    pub response: Option<ResponseInfo>,
+    /// The links found in the document, from the configured `link_sources`,
+    /// whether or not they were followed. Only visited HTML documents, and
+    /// stylesheets when a CSS source is configured, have links.
+    pub links: Vec<Link>,
+    /// Whether the page asked not to be indexed, through a
+    /// `<meta name="robots">` element or `X-Robots-Tag` header.
+    pub noindex: bool,
+    /// The canonical URL the page declared for itself with
+    /// `<link rel="canonical">`, in its canonical form. Pages exposed under
+    /// several URLs declare the same canonical URL from each of them. See
+    /// `canonical_clusters`.
+    pub canonical: Option<Url>,
+    /// The data the crawl's `Extractor` extracted from the document. Only
+    /// visited HTML documents have data.
+    pub data: Option<T>,
+}
+
+impl<T> CrawlResult<T> {
+    /// Returns `true` unless the URL was skipped, so also for URLs that
+    /// failed; use `error` to tell those apart. Skipped URLs that redirected
+    /// were requested, but their documents were not retrieved.
+    pub fn is_visited(&self) -> bool {
+        !matches!(self.outcome, CrawlOutcome::Skipped(_))
+    }
+
+    /// Returns the error the URL failed with, if any.
+    pub fn error(&self) -> Option<&Error> {
+        match &self.outcome {
+            CrawlOutcome::Failed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Groups the pages visited in `results` into clusters of duplicates, keyed
+/// by the canonical URL they declared. Pages declaring no canonical URL are
+/// keyed by their own URL. The URLs of each cluster are in the order of
+/// `results`.
+///
+/// Only pages that were successfully visited are clustered. A canonical URL
+/// that was not itself crawled still keys the cluster of the pages naming it.
+pub fn canonical_clusters<'a, I, T>(results: I) -> BTreeMap<Url, Vec<Url>>
+where
+    I: IntoIterator<Item = &'a CrawlResult<T>>,
+    T: 'a,
+{
+    let mut clusters = BTreeMap::<Url, Vec<Url>>::new();
+    for result in results {
+        if result.outcome != CrawlOutcome::Visited {
+            continue;
+        }
+        let canonical = result.canonical.as_ref().unwrap_or(&result.url);
+        clusters
+            .entry(canonical.clone())
+            .or_default()
+            .push(result.url.clone());
+    }
+    clusters
+}
+
+/// What the crawl did with a URL it came across.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CrawlOutcome {
+    /// The URL was requested. HTML documents are read and their links
+    /// followed; other resources are recorded without reading their body.
+    Visited,
+    /// The URL was requested, but the request failed. The links of a failed
+    /// page are not followed. A `robots.txt` file that could not be archived
+    /// is reported with a failed result of its own, though its rules apply.
+    Failed(Error),
+    /// The URL was in scope, but its document was not retrieved. Most
+    /// skipped URLs were never requested. Those that redirected somewhere the
+    /// crawl would not follow were, and have the redirect as their
+    /// `response`.
+    Skipped(SkipReason),
+}
+
+/// Why the document of an in-scope URL was not retrieved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The site's `robots.txt` disallows the URL for our product token, so it
+    /// was not requested, or the URL redirected to one it disallows. Sites
+    /// whose `robots.txt` cannot be reached are treated as disallowing every
+    /// URL.
+    RobotsTxt,
+    /// The URL was requested, but redirected to a URL outside the crawl's
+    /// scope, which was not. The redirects are on the result's `response`.
+    RedirectOutOfScope,
 }
 
 /// Returns a Stream that runs over all URLs in the given domain of `url`.
@@ -22,7 +193,8 @@ pub struct CrawlResult {
 /// control back to the caller as it goes.
 ///
 /// For multiple requests it is recommended you use the same client across
-/// requests. See `crawl_domain_with_client`.
+/// requests. See `crawl_domain_with_client`. To tune the crawl, see
+/// `CrawlerBuilder`.
 ///
 /// ## Example
 ///
@@ -52,26 +224,20 @@ pub struct CrawlResult {
 /// Got https://www.linuxmint.com/
 /// Got https://www.linuxmint.com/topstories/topstories.css
 /// Got https://www.linuxmint.com/teams.php
-/// Got https://www.linuxmint.com/teams.php#content
 /// Got https://www.linuxmint.com/store.php
 /// Got https://www.linuxmint.com/store_tshirts.php
-/// Got https://www.linuxmint.com/store_tshirts.php#content
 /// Got https://www.linuxmint.com/store_mintbox3.php
-/// Got https://www.linuxmint.com/store_mintbox3.php#content
 /// Got https://www.linuxmint.com/store_mintbox.php
-/// Got https://www.linuxmint.com/store_mintbox.php#content
 /// Got https://www.linuxmint.com/store_computers.php
-/// Got https://www.linuxmint.com/store_computers.php#content
-/// Got https://www.linuxmint.com/store.php#content
 /// Got https://www.linuxmint.com/sponsors.php
 /// Got https://www.linuxmint.com/sponsors_info.php
-/// Got https://www.linuxmint.com/sponsors_info.php#content
-/// Got https://www.linuxmint.com/sponsors.php#content
 /// Got https://www.linuxmint.com/screenshots.php
 /// ...
 /// ```
-pub fn crawl_domain(url: Url) -> Result<impl Stream<Item = CrawlResult>> {
-    let builder = ClientBuilder::new();
+pub fn crawl_domain(url: Url) -> Result<CrawlStream> {
+    let builder = ClientBuilder::new()
+        .redirect(Policy::none())
+        .http1_title_case_headers();
     let client = builder.build()?;
     Ok(crawl_domain_with_client(client, url))
 }
@@ -79,18 +245,44 @@ pub fn crawl_domain(url: Url) -> Result<impl Stream<Item = CrawlResult>> {
 /// Alternative to `crawl_domain` that accepts a `client` object.
 ///
 /// In cases where multiple requests are made, reuse of the same client is
-/// better than creating a new `Client` object for each call.
-pub fn crawl_domain_with_client(client: Client, url: Url) -> impl Stream<Item = CrawlResult> {
-    let init_state = CrawlStreamState::create(client, url);
-    // From our initial state attempt to generate a stream.
-    stream::unfold(init_state, |state| state.pop_document())
+/// better than creating a new `Client` object for each call. `client` is
+/// either a `reqwest::Client` or a `RateLimitedFetcher`.
+///
+/// Host limits are per fetcher, not per client. A `reqwest::Client` is given
+/// a rate limiter of its own, applying `DEFAULT_HOST_LIMITS`, on every call,
+/// so calls given clones of the same client do not share limits. Calls given
+/// clones of the same `RateLimitedFetcher` do.
+///
+/// Up to `DEFAULT_CONCURRENCY` pages are requested at once, within the host
+/// limits. See `crawl_domain_concurrent_with_client` to change this.
+pub fn crawl_domain_with_client(client: impl Into<RateLimitedFetcher>, url: Url) -> CrawlStream {
+    crawl_domain_concurrent_with_client(client, url, DEFAULT_CONCURRENCY)
+}
+
+/// Alternative to `crawl_domain_with_client` that keeps up to `concurrency`
+/// page requests in flight at once.
+///
+/// Results are yielded in the order their requests complete, rather than the
+/// order they were queued. A `concurrency` of `0` is treated as `1`.
+pub fn crawl_domain_concurrent_with_client(
+    client: impl Into<RateLimitedFetcher>,
+    url: Url,
+    concurrency: usize,
+) -> CrawlStream {
+    let config = CrawlConfig {
+        concurrency,
+        ..CrawlConfig::default()
+    };
+    Crawler::with_fetcher(client.into(), config).crawl(url)
 }
 
 /// Returns a complete list of all URLs visited in the given domain of `url`.
+/// URLs that were skipped are not included.
 ///  
 /// This task does not complete until all URLs are visited and as such may not
 /// be suitable for large domains. See `crawl_domain` for the `Stream`
-/// equivalent to this `Future`.
+/// equivalent to this `Future`, or `CrawlerBuilder` to set budgets that bound
+/// the crawl.
 ///
 /// For multiple requests it is recommended you use the same client across
 /// requests. See `unique_url_list_with_client`.
@@ -123,7 +315,9 @@ pub fn crawl_domain_with_client(client: Client, url: Url) -> impl Stream<Item =
 /// Url found: https://www.enhance.com/favicon.ico
 /// ```
 pub async fn unique_url_list(url: Url) -> Result<vec::Vec<Url>> {
-    let builder = ClientBuilder::new();
+    let builder = ClientBuilder::new()
+        .redirect(Policy::none())
+        .http1_title_case_headers();
     let client = builder.build()?;
     Ok(unique_url_list_with_client(client, url).await)
 }
@@ -131,19 +325,25 @@ pub async fn unique_url_list(url: Url) -> Result<vec::Vec<Url>> {
 /// Alternative to `unique_url_list` that accepts a `client` object.
 ///
 /// In cases where multiple requests are made, reuse of the same client is
-/// better than creating a new `Client` object for each call.
-pub async fn unique_url_list_with_client(client: Client, url: Url) -> vec::Vec<Url> {
-    crawl_domain_with_client(client, url)
-        .map(|r| r.url)
-        .collect()
+/// better than creating a new `Client` object for each call. As with
+/// `crawl_domain_with_client`, calls given clones of one `RateLimitedFetcher`
+/// share its host limits.
+pub async fn unique_url_list_with_client(
+    client: impl Into<RateLimitedFetcher>,
+    url: Url,
+) -> vec::Vec<Url> {
+    Crawler::with_fetcher(client.into(), CrawlConfig::default())
+        .unique_url_list(url)
         .await
 }
 
 /// Returns a complete count of all URLs visited in the given domain of `url`.
+/// URLs that were skipped are not counted.
 ///  
 /// This task does not complete until all URLs are visited and as such may not
 /// be suitable for large domains. See `crawl_domain` for the `Stream`
-/// equivalent to this `Future`.
+/// equivalent to this `Future`, or `CrawlerBuilder` to set budgets that bound
+/// the crawl.
 ///
 /// For multiple requests it is recommended you use the same client across
 /// requests. See `unique_url_count_with_client`.
@@ -172,7 +372,9 @@ pub async fn unique_url_list_with_client(client: Client, url: Url) -> vec::Vec<U
 /// Urls found: 3
 /// ```
 pub async fn unique_url_count(url: Url) -> Result<usize> {
-    let builder = ClientBuilder::new();
+    let builder = ClientBuilder::new()
+        .redirect(Policy::none())
+        .http1_title_case_headers();
     let client = builder.build()?;
     Ok(unique_url_count_with_client(client, url).await)
 }
@@ -180,138 +382,1041 @@ pub async fn unique_url_count(url: Url) -> Result<usize> {
 /// Alternative to `unique_url_count` that accepts a `client` object.
 ///
 /// In cases where multiple requests are made, reuse of the same client is
-/// better than creating a new `Client` object for each call.
-pub async fn unique_url_count_with_client(client: Client, url: Url) -> usize {
-    crawl_domain_with_client(client, url)
-        .fold(0, |i, _| futures::future::ready(i + 1))
+/// better than creating a new `Client` object for each call. As with
+/// `crawl_domain_with_client`, calls given clones of one `RateLimitedFetcher`
+/// share its host limits.
+pub async fn unique_url_count_with_client(
+    client: impl Into<RateLimitedFetcher>,
+    url: Url,
+) -> usize {
+    Crawler::with_fetcher(client.into(), CrawlConfig::default())
+        .unique_url_count(url)
         .await
 }
 
+/// A pending request for the document at a URL, resolving to the URL, the
+/// response received, if any, and the body of the document.
+type DocumentFuture = BoxFuture<'static, (Url, Option<ResponseInfo>, DocumentResult)>;
+
+/// What was retrieved for a document, or why it could not be retrieved.
+type DocumentResult = std::result::Result<Body, Error>;
+
+/// What was retrieved for a document.
+#[derive(Debug)]
+enum Body {
+    /// The text of an HTML document.
+    Html(String),
+    /// The text of a CSS stylesheet.
+    Css(String),
+    /// A resource that is not HTML, whose body was not downloaded.
+    NotHtml,
+    /// A redirect out of the crawl's scope, which was not followed.
+    RedirectOutOfScope,
+    /// A redirect to follow, to where the last of the response's redirects
+    /// leads.
+    Redirect,
+}
+
+/// A URL waiting to be visited, along with how it was reached.
+#[derive(Clone, Debug)]
+struct QueuedUrl {
+    /// The URL to visit, in its canonical form. This is the URL recorded as
+    /// visited, while `original` is the one requested.
+    url: Url,
+    /// The URL as it was found, before canonicalization.
+    original: Url,
+    /// The number of links followed from the starting URL to reach `url`.
+    depth: usize,
+    /// The page `url` was found on, if it was found by following a link.
+    referrer: Option<Url>,
+    /// The last response for `url`, when it redirected. The redirect is
+    /// followed once where it leads has been checked like any queued URL.
+    redirected: Option<Box<ResponseInfo>>,
+}
+
+impl QueuedUrl {
+    /// The URL to request next: `original`, or where it last redirected to.
+    fn target(&self) -> &Url {
+        let redirect = self.redirected.as_ref().and_then(|r| r.redirects.last());
+        redirect.map_or(&self.original, |redirect| &redirect.to)
+    }
+}
+
+impl Ord for QueuedUrl {
+    /// Orders shallower URLs first, so that the to-visit queue is crawled
+    /// breadth-first and each URL is visited at the shallowest depth known.
+    /// Redirects being followed go before new URLs of the same depth.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .depth
+            .cmp(&self.depth)
+            .then_with(|| self.redirected.is_some().cmp(&other.redirected.is_some()))
+            .then_with(|| self.url.cmp(&other.url))
+            .then_with(|| self.original.cmp(&other.original))
+            .then_with(|| self.referrer.cmp(&other.referrer))
+            .then_with(|| self.target().cmp(other.target()))
+    }
+}
+
+impl PartialEq for QueuedUrl {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedUrl {}
+
+impl PartialOrd for QueuedUrl {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The outcome of a request made during a crawl.
+// Nearly every request is for a document, so boxing them to shrink the other
+// variants would only add an allocation.
+#[allow(clippy::large_enum_variant)]
+enum Fetched {
+    /// The response and body of the document for a queued URL.
+    Document(QueuedUrl, Option<ResponseInfo>, DocumentResult),
+    /// The `robots.txt` rules for the origin with the given serialization,
+    /// and whether the exchanges for the file were archived.
+    Robots(String, Robots, std::result::Result<(), Error>),
+    /// The body of the sitemap at a URL, if it could be retrieved.
+    Sitemap(Option<Vec<u8>>),
+}
+
+/// What is known of the `robots.txt` rules for an origin.
+enum RobotsEntry {
+    /// The rules are being fetched. Pages and sitemaps on the origin wait
+    /// here until they arrive.
+    Pending {
+        /// The queued URLs waiting on the rules.
+        pages: Vec<QueuedUrl>,
+        /// The sitemaps waiting on the rules.
+        sitemaps: Vec<Url>,
+    },
+    /// The rules have arrived.
+    Ready(Robots),
+}
+
 /// The current state of the CrawlStream.
-struct CrawlStreamState {
-    /// The client used to do crawl requests.
-    client: Client,
+struct CrawlStreamState<T> {
+    /// What makes the crawl's requests.
+    fetcher: Arc<dyn Fetcher>,
+    /// The policy this crawl runs under.
+    config: Arc<CrawlConfig>,
+    /// Extracts the data attached to the result of each HTML document.
+    extractor: Arc<dyn Extractor<Output = T>>,
+    /// The URL the crawl started from, against which scope is checked.
+    seed: Url,
+    /// The number of URLs dispatched so far, checked against `max_pages`.
+    dispatched: usize,
     /// The collection of unique URLS that have already been processed.
     visited: HashSet<Url>,
     /// Collection of queued items to visit, may contain duplicates.
-    to_visit: BinaryHeap<Url>,
+    to_visit: BinaryHeap<QueuedUrl>,
+    /// Requests that have been sent but whose responses have not yet been
+    /// processed.
+    in_flight: FuturesUnordered<BoxFuture<'static, Fetched>>,
+    /// The `robots.txt` rules of each origin seen so far, keyed by the
+    /// origin's serialization.
+    robots: HashMap<String, RobotsEntry>,
+    /// Results that are ready to be yielded without waiting on a request.
+    ready: VecDeque<CrawlResult<T>>,
+    /// The origins whose sitemaps have been looked for.
+    sitemap_origins: HashSet<String>,
+    /// The sitemaps that have been queued, at most `MAX_SITEMAPS` of them.
+    sitemaps: HashSet<Url>,
+    /// Sitemaps waiting to be requested.
+    sitemap_queue: VecDeque<Url>,
+    /// When the crawl started, checked against `max_duration`.
+    started: Instant,
+    /// The number of body bytes downloaded so far, checked against
+    /// `max_bytes`. Requests add to this as they complete.
+    downloaded: Arc<AtomicU64>,
+    /// The budget that ended the crawl, once one has.
+    exhausted: BudgetReport,
+}
+
+/// Builds a `method` request for `url`, with the user agent and timeout of
+/// `config`. A user agent that is not a valid header value is left out.
+fn request(config: &CrawlConfig, method: Method, url: Url) -> FetchRequest {
+    let mut headers = HeaderMap::new();
+    if let Some(user_agent) = &config.user_agent {
+        if let Ok(user_agent) = HeaderValue::from_str(user_agent) {
+            headers.insert(USER_AGENT, user_agent);
+        }
+    }
+    FetchRequest {
+        method,
+        url,
+        headers,
+        timeout: config.timeout,
+    }
+}
+
+/// Archives `request`, sent at `sent`, and its `response`, whose body was
+/// read as `payload`, if `config` has a WARC writer. `truncated` gives why
+/// `payload` is not the whole body, if it is not.
+async fn archive(
+    config: &CrawlConfig,
+    request: &FetchRequest,
+    sent: SystemTime,
+    response: &ResponseInfo,
+    payload: &[u8],
+    truncated: Option<Truncated>,
+) -> std::result::Result<(), Error> {
+    match &config.warc {
+        Some(warc) => warc
+            .write_exchange(request, response, payload, truncated, sent)
+            .await
+            .map_err(|e| Error::Archive(e.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Why the body of `response` is missing from its archived record when it
+/// was not read: not at all if it was empty.
+fn unread(response: &ResponseInfo) -> Option<Truncated> {
+    match response.content_length {
+        Some(0) => None,
+        _ => Some(Truncated::Unspecified),
+    }
+}
+
+/// Why a body that failed to be read with `e` is missing from its archived
+/// record.
+fn truncation(e: &Error) -> Truncated {
+    match e {
+        Error::TooLarge(_) => Truncated::Length,
+        Error::Timeout => Truncated::Time,
+        _ => Truncated::Disconnect,
+    }
+}
+
+/// The response to a GET request for a `robots.txt` file or sitemap, if it
+/// came within `MAX_FILE_REDIRECTS` redirects, or why it could not be made.
+type FileResult = std::result::Result<Option<FileResponse>, Error>;
+
+/// The response to a GET request for a `robots.txt` file or sitemap, with
+/// what is needed to archive the exchange once its body is read.
+struct FileResponse {
+    /// The request made.
+    request: FetchRequest,
+    /// When the request was sent.
+    sent: SystemTime,
+    /// When the request was made, for timing the response.
+    start: Instant,
+    /// The response, whose body has not been read.
+    response: FetchResponse,
+}
+
+impl FileResponse {
+    /// Archives the exchange without reading the body, as for a redirect or
+    /// an error status, if `config` has a WARC writer.
+    async fn skip(self, config: &CrawlConfig) -> std::result::Result<(), Error> {
+        let info = ResponseInfo::new(&self.response, self.start.elapsed());
+        archive(config, &self.request, self.sent, &info, &[], unread(&info)).await
+    }
+
+    /// Reads the body with `read`, which resolves to the bytes read and
+    /// whether it stopped short of the end, and archives the exchange if
+    /// `config` has a WARC writer.
+    ///
+    /// Returns the body, and whether the exchange was archived. A body read
+    /// is returned even if it could not be archived.
+    async fn read<F, R>(
+        self,
+        config: &CrawlConfig,
+        read: F,
+    ) -> (
+        std::result::Result<Vec<u8>, Error>,
+        std::result::Result<(), Error>,
+    )
+    where
+        F: FnOnce(FetchResponse) -> R,
+        R: Future<Output = std::result::Result<(Vec<u8>, bool), Error>>,
+    {
+        let mut info = ResponseInfo::new(&self.response, Duration::default());
+        let body = read(self.response).await;
+        info.elapsed = self.start.elapsed();
+        let (payload, truncated) = match &body {
+            Ok((body, cut)) => (&body[..], Some(Truncated::Length).filter(|_| *cut)),
+            Err(e) => (&[][..], Some(truncation(e))),
+        };
+        let archived = archive(config, &self.request, self.sent, &info, payload, truncated).await;
+        (body.map(|(body, _)| body), archived)
+    }
 }
 
-impl CrawlStreamState {
-    /// Initializes a `CrawlStreamState` with a `Client`, `client`, for HTTP
-    /// requests, and a URL, `url`, to be the starting point for crawling a
-    /// particular domain.
-    fn create(client: Client, url: Url) -> Self {
+impl<T: Send + 'static> CrawlStreamState<T> {
+    /// Initializes a `CrawlStreamState` with a `Fetcher`, `fetcher`, to make
+    /// requests, the policy `config` to crawl under, the `extractor` of each
+    /// document's data, and a URL, `url`, to be the starting point for
+    /// crawling a particular domain.
+    fn create(
+        fetcher: Arc<dyn Fetcher>,
+        config: Arc<CrawlConfig>,
+        extractor: Arc<dyn Extractor<Output = T>>,
+        url: Url,
+    ) -> Self {
+        let canonical = config.canonicalizer.canonicalize(&url);
         Self {
-            client,
+            fetcher,
+            config,
+            extractor,
+            seed: canonical.clone(),
+            dispatched: 0,
             visited: Default::default(),
             to_visit: {
-                let mut v = BinaryHeap::<Url>::default();
-                v.push(url);
+                let mut v = BinaryHeap::<QueuedUrl>::default();
+                v.push(QueuedUrl {
+                    url: canonical,
+                    original: url,
+                    depth: 0,
+                    referrer: None,
+                    redirected: None,
+                });
                 v
             },
+            in_flight: Default::default(),
+            robots: Default::default(),
+            ready: Default::default(),
+            sitemap_origins: Default::default(),
+            sitemaps: Default::default(),
+            sitemap_queue: Default::default(),
+            started: Instant::now(),
+            downloaded: Default::default(),
+            exhausted: Default::default(),
         }
     }
 
-    /// Adds the given URL, `url` to our list of URLs that are to be visited.
+    /// Adds the given URL, `url`, reached by following `depth` links from the
+    /// starting URL, to our list of URLs that are to be visited. `referrer`
+    /// is the page it was found on, if any.
+    ///
+    /// The URL is canonicalized first, so that URLs differing only in ways
+    /// the configured `Canonicalizer` removes are treated as one.
     ///
     /// This function returns `None` if `url` already exists in our collection
     /// of already visited URLs, otherwise `Some(())` is returned when the `url`
     /// is successfully added to the queue of URLs to visit. The same URL can be
     /// added successfully multiple times if it has not been visited at least
     /// once.
-    fn add_url_to_queue(&mut self, url: Url) -> Option<()> {
-        if self.visited.contains(&url) {
+    fn add_url_to_queue(&mut self, url: Url, depth: usize, referrer: Option<Url>) -> Option<()> {
+        let canonical = self.config.canonicalizer.canonicalize(&url);
+        if self.visited.contains(&canonical) {
             return None;
         }
-        self.to_visit.push(url);
+        self.to_visit.push(QueuedUrl {
+            url: canonical,
+            original: url,
+            depth,
+            referrer,
+            redirected: None,
+        });
         Some(())
     }
 
-    /// Given a URL, url, attempts to retrieve the document for the given URL
-    /// and returns the result in a `CrawlResult`.
+    /// Returns a future making a GET request for `url` with the configured
+    /// user agent and timeout, for a `robots.txt` file or sitemap.
     ///
-    /// All documents are retrieved via the GET HTTP method.
-    async fn document_for_url(&self, url: &Url) -> Result<Html> {
-        let res = self.client.get(url.clone()).send().await;
-        match res {
-            Err(e) => Err(e),
-            Ok(t) => {
-                let body = t.text_with_charset("utf-8").await;
-                match body {
-                    Err(e) => Err(e),
-                    Ok(html) => Ok(Html::parse_document(html.as_str())),
+    /// Redirects are followed, up to `MAX_FILE_REDIRECTS` of them, each
+    /// archived as it is. The future resolves to `None` when there are more.
+    ///
+    /// The future also resolves to whether every redirect was archived. A
+    /// redirect that could not be archived is followed all the same.
+    fn get(&self, url: Url) -> BoxFuture<'static, (FileResult, std::result::Result<(), Error>)> {
+        let fetcher = self.fetcher.clone();
+        let config = self.config.clone();
+        Box::pin(async move {
+            let mut url = url;
+            let mut archived = Ok(());
+            for _ in 0..=MAX_FILE_REDIRECTS {
+                let request = request(&config, Method::GET, url);
+                let start = Instant::now();
+                let sent = SystemTime::now();
+                let response = match fetcher.fetch(request.clone()).await {
+                    Ok(response) => response,
+                    Err(e) => return (Err(e), archived),
+                };
+                let next = redirect_location(&response);
+                let file = FileResponse {
+                    request,
+                    sent,
+                    start,
+                    response,
+                };
+                match next {
+                    Some(next) => {
+                        archived = archived.and(file.skip(&config).await);
+                        url = next;
+                    }
+                    None => return (Ok(Some(file)), archived),
                 }
             }
+            (Ok(None), archived)
+        })
+    }
+
+    /// Gives the fetcher's rate limiter the `Crawl-delay` of the `robots.txt`
+    /// of `url`'s origin, if it has one, ahead of a request to `url`. The
+    /// delay is given again before each request, in case the limiter forgot
+    /// the host while it was idle.
+    fn remind_crawl_delay(&self, url: &Url) {
+        let limiter = match self.fetcher.rate_limiter() {
+            None => return,
+            Some(limiter) => limiter,
+        };
+        let origin = url.origin().ascii_serialization();
+        if let Some(RobotsEntry::Ready(robots)) = self.robots.get(&origin) {
+            if let Some(delay) = robots.crawl_delay() {
+                limiter.set_crawl_delay(url, delay);
+            }
         }
     }
 
-    /// Given a URL, `url` and a html-document `html`, goes through all valid
-    /// href tags in the document, and if they have the same domain/scheme as
-    /// `url`, append them to the to-visit queue when applicable.
-    fn push_document_links(&mut self, document_url: &Url, html: &Html) {
-        let selector = Selector::parse("a, link").unwrap();
-        let urls = html
-            .select(&selector)
-            .filter_map(|n| n.value().attr("href"))
-            .filter_map(|raw_url| {
-                Url::from_str(raw_url)
-                    .or_else(|e| {
-                        // If the URL is relative then `Url::parse` will fail.
-                        // We can try again by using the document URL
-                        // as our base.
-                        if e == url::ParseError::RelativeUrlWithoutBase {
-                            document_url.join(raw_url)
-                        } else {
-                            Err(e)
+    /// Given a queued URL, `queued`, returns a future that attempts to
+    /// retrieve the body of its document, along with what was learnt from
+    /// the response. The URL is requested as it was found, rather than in its
+    /// canonical form, which a server may not recognise.
+    ///
+    /// Redirects are followed one at a time: a redirect resolves to
+    /// `Body::Redirect`, so that the crawl checks where it leads like any
+    /// queued URL before requesting it. A redirect that loops, goes beyond
+    /// the configured `max_redirects` or leads out of the crawl's scope ends
+    /// the document here, without a body. Every hop is recorded on the
+    /// response.
+    ///
+    /// Responses with an error status fail without their body being read, as
+    /// do bodies larger than the configured `max_body_size`. Bodies that are
+    /// not HTML are not read at all. With `head_first`, a HEAD request that
+    /// finds a resource that is not HTML stands in for the GET.
+    ///
+    /// The future does not borrow the state, so many may run at once. All
+    /// documents are retrieved via the GET HTTP method, with the configured
+    /// user agent and timeout.
+    fn document_for_url(&self, queued: &QueuedUrl) -> DocumentFuture {
+        let fetcher = self.fetcher.clone();
+        let config = self.config.clone();
+        let seed = self.seed.clone();
+        let downloaded = self.downloaded.clone();
+        let url = queued.url.clone();
+        let current = queued.target().clone();
+        // The time taken by earlier hops counts towards the document's.
+        let (mut redirects, before) = match &queued.redirected {
+            Some(response) => (response.redirects.clone(), response.elapsed),
+            None => (Vec::new(), Duration::default()),
+        };
+        Box::pin(async move {
+            let start = Instant::now();
+            let elapsed = || before + start.elapsed();
+            // Stylesheets are only read when there are links to find in them.
+            let read_css = config.link_sources.iter().any(|source| source.is_css());
+            if config.head_first && redirects.is_empty() {
+                let head = request(&config, Method::HEAD, current.clone());
+                let sent = SystemTime::now();
+                if let Ok(response) = fetcher.fetch(head.clone()).await {
+                    let info = ResponseInfo::new(&response, elapsed());
+                    // Responses to HEAD requests have no body to archive.
+                    if let Err(e) = archive(&config, &head, sent, &info, &[], None).await {
+                        return (url, Some(info), Err(e));
+                    }
+                    // Anything that might need a GET, such as a redirect or
+                    // an error a HEAD request alone may cause, gets one.
+                    let content_type = info.content_type.as_deref();
+                    let wanted = is_html(content_type) || (read_css && is_css(content_type));
+                    if info.status.is_success() && !wanted {
+                        return (url, Some(info), Ok(Body::NotHtml));
+                    }
+                }
+            }
+            let get = request(&config, Method::GET, current.clone());
+            let sent = SystemTime::now();
+            let response = match fetcher.fetch(get.clone()).await {
+                Err(e) => return (url, None, Err(e)),
+                Ok(response) => response,
+            };
+            if let Some(next) = redirect_location(&response) {
+                redirects.push(Redirect {
+                    from: current,
+                    status: response.status,
+                    to: next.clone(),
+                });
+                let mut info = ResponseInfo::new(&response, elapsed());
+                info.redirects = redirects;
+                let truncated = unread(&info);
+                if let Err(e) = archive(&config, &get, sent, &info, &[], truncated).await {
+                    return (url, Some(info), Err(e));
+                }
+                if info.redirects.iter().any(|r| r.from == next) {
+                    return (url, Some(info), Err(Error::RedirectLoop(next)));
+                }
+                if info.redirects.len() > config.max_redirects {
+                    let error = Error::TooManyRedirects(config.max_redirects);
+                    return (url, Some(info), Err(error));
+                }
+                if !config.should_follow(&seed, &next) {
+                    return (url, Some(info), Ok(Body::RedirectOutOfScope));
+                }
+                return (url, Some(info), Ok(Body::Redirect));
+            }
+
+            let mut info = ResponseInfo::new(&response, elapsed());
+            info.redirects = redirects;
+            let status = response.status;
+            let css = read_css && info.is_css();
+            // A fetcher that follows redirects itself only reveals where they
+            // ended.
+            let out_of_scope =
+                response.url != current && !config.should_follow(&seed, &response.url);
+            let failed = status.is_client_error() || status.is_server_error();
+            let body = if out_of_scope || failed || !(info.is_html() || css) {
+                None
+            } else {
+                let body = read_body(response, config.max_body_size, &downloaded).await;
+                info.elapsed = elapsed();
+                Some(body)
+            };
+
+            let (payload, truncated) = match &body {
+                None => (&[][..], unread(&info)),
+                Some(Ok(body)) => (&body[..], None),
+                Some(Err(e)) => (&[][..], Some(truncation(e))),
+            };
+            let archived = archive(&config, &get, sent, &info, payload, truncated).await;
+            if let Err(e) = archived {
+                return (url, Some(info), Err(e));
+            }
+            let body = match body {
+                None if out_of_scope => Ok(Body::RedirectOutOfScope),
+                None if failed => Err(Error::HttpStatus(status)),
+                None => Ok(Body::NotHtml),
+                Some(body) => body.map(|body| {
+                    info.content_length = info.content_length.or(Some(body.len() as u64));
+                    let content_type = info.content_type.as_deref();
+                    if css {
+                        Body::Css(charset::decode_css(&body, content_type))
+                    } else {
+                        Body::Html(charset::decode(&body, content_type))
+                    }
+                }),
+            };
+            (url, Some(info), body)
+        })
+    }
+
+    /// Returns a future that retrieves the `robots.txt` rules for the origin
+    /// of `url`, whose serialization is `origin`.
+    ///
+    /// Following RFC 9309, a missing file (any 4xx status) allows everything,
+    /// as does one behind more than `MAX_FILE_REDIRECTS` redirects, while a
+    /// file that cannot be reached disallows everything. Only the first
+    /// `MAX_ROBOTS_SIZE` bytes of the file are read. Whether the file could be
+    /// archived has no bearing on the rules. A URL that cannot have a
+    /// `robots.txt` file is allowed everything.
+    fn robots_for_url(&self, url: &Url, origin: String) -> BoxFuture<'static, Fetched> {
+        let request = url.join("/robots.txt").ok().map(|url| self.get(url));
+        let config = self.config.clone();
+        let downloaded = self.downloaded.clone();
+        Box::pin(async move {
+            let (response, mut archived) = match request {
+                Some(request) => request.await,
+                None => (Ok(None), Ok(())),
+            };
+            let robots = match response {
+                Ok(Some(file)) if file.response.status.is_success() => {
+                    let read = |response| read_body_prefix(response, MAX_ROBOTS_SIZE, &downloaded);
+                    let (body, file_archived) = file.read(&config, read).await;
+                    archived = archived.and(file_archived);
+                    match body {
+                        Ok(mut body) => {
+                            // A line cut off at the limit is left out.
+                            if body.len() as u64 == MAX_ROBOTS_SIZE {
+                                let end = body.iter().rposition(|&b| b == b'\n');
+                                body.truncate(end.unwrap_or(0));
+                            }
+                            Robots::parse(&String::from_utf8_lossy(&body), &config.robots_agent)
                         }
-                    })
-                    .ok()
-            })
-            // Ensure URL is tied to our domain and is a http/https scheme.
-            .filter(|url| {
-                document_url.domain() == url.domain()
-                    && (url.scheme() == "http" || url.scheme() == "https")
-            });
+                        Err(_) => Robots::disallow_all(),
+                    }
+                }
+                Ok(Some(file)) => {
+                    let missing = file.response.status.is_client_error();
+                    archived = archived.and(file.skip(&config).await);
+                    if missing {
+                        Robots::allow_all()
+                    } else {
+                        Robots::disallow_all()
+                    }
+                }
+                Ok(None) => Robots::allow_all(),
+                Err(_) => Robots::disallow_all(),
+            };
+            Fetched::Robots(origin, robots, archived)
+        })
+    }
+
+    /// Records the `robots` rules for `origin`, and returns every URL that was
+    /// waiting on them to the to-visit queue. Any `Crawl-delay` is passed on
+    /// to the rate limiter, and any sitemaps the rules list are requested when
+    /// configured. A failure to archive the file, `archived`, is returned as
+    /// a failed result for the file's URL.
+    fn set_robots(
+        &mut self,
+        origin: String,
+        robots: Robots,
+        archived: std::result::Result<(), Error>,
+    ) {
+        let robots_url = Url::parse(&origin).and_then(|origin| origin.join("/robots.txt"));
+        if let (Err(e), Ok(robots_url)) = (archived, robots_url) {
+            let queued = QueuedUrl {
+                url: robots_url.clone(),
+                original: robots_url,
+                depth: 0,
+                referrer: None,
+                redirected: None,
+            };
+            self.ready.push_back(Self::result_without_document(
+                queued,
+                CrawlOutcome::Failed(e),
+            ));
+        }
+        let limiter = self.fetcher.rate_limiter();
+        if let (Some(limiter), Some(delay)) = (limiter, robots.crawl_delay()) {
+            if let Ok(origin_url) = Url::parse(&origin) {
+                limiter.set_crawl_delay(&origin_url, delay);
+            }
+        }
+        self.discover_sitemaps(&origin, robots.sitemaps());
+        let previous = self.robots.insert(origin, RobotsEntry::Ready(robots));
+        if let Some(RobotsEntry::Pending { pages, sitemaps }) = previous {
+            self.to_visit.extend(pages);
+            self.sitemap_queue.extend(sitemaps);
+        }
+    }
+
+    /// Checks the URL `queued` requests next against the `robots.txt` rules
+    /// of its origin.
+    ///
+    /// Returns `Some(queued)` if the URL may be requested now. Otherwise the
+    /// URL is either parked until the rules arrive, or recorded as skipped,
+    /// and `None` is returned. URLs with an opaque origin, such as `mailto:`
+    /// URLs, have no `robots.txt` to check, and may always be requested.
+    fn check_robots(&mut self, queued: QueuedUrl) -> Option<QueuedUrl> {
+        let target = queued.target().clone();
+        if !self.config.respect_robots_txt || !target.origin().is_tuple() {
+            return Some(queued);
+        }
+        let origin = target.origin().ascii_serialization();
+        match self.robots.get_mut(&origin) {
+            Some(RobotsEntry::Ready(robots)) => {
+                if robots.is_allowed(&target) {
+                    return Some(queued);
+                }
+                // A URL that redirects somewhere disallowed is recorded as
+                // visited when first requested. Where it redirects to is left
+                // to be recorded as skipped if it is found itself.
+                if queued.redirected.is_none() {
+                    self.visited.insert(queued.url.clone());
+                }
+                let outcome = CrawlOutcome::Skipped(SkipReason::RobotsTxt);
+                self.ready
+                    .push_back(Self::result_without_document(queued, outcome));
+            }
+            Some(RobotsEntry::Pending { pages, .. }) => pages.push(queued),
+            None => {
+                let request = self.robots_for_url(&target, origin.clone());
+                self.in_flight.push(request);
+                let pending = RobotsEntry::Pending {
+                    pages: vec![queued],
+                    sitemaps: Vec::new(),
+                };
+                self.robots.insert(origin, pending);
+            }
+        }
+        None
+    }
+
+    /// Checks the sitemap `url` against the `robots.txt` rules of its origin.
+    ///
+    /// Returns `Some(url)` if the sitemap may be requested now. Otherwise the
+    /// sitemap is either parked until the rules arrive, or dropped, and
+    /// `None` is returned. Sitemaps with an opaque origin have no
+    /// `robots.txt` to check.
+    fn check_sitemap_robots(&mut self, url: Url) -> Option<Url> {
+        if !self.config.respect_robots_txt || !url.origin().is_tuple() {
+            return Some(url);
+        }
+        let origin = url.origin().ascii_serialization();
+        match self.robots.get_mut(&origin) {
+            Some(RobotsEntry::Ready(robots)) => {
+                return Some(url).filter(|url| robots.is_allowed(url))
+            }
+            Some(RobotsEntry::Pending { sitemaps, .. }) => sitemaps.push(url),
+            None => {
+                let request = self.robots_for_url(&url, origin.clone());
+                self.in_flight.push(request);
+                let pending = RobotsEntry::Pending {
+                    pages: Vec::new(),
+                    sitemaps: vec![url],
+                };
+                self.robots.insert(origin, pending);
+            }
+        }
+        None
+    }
+
+    /// Queues the sitemaps of `origin` the first time it is seen, if the
+    /// configuration asks for sitemaps. These are the sitemaps in `listed`,
+    /// typically from `robots.txt`, along with `/sitemap.xml`.
+    fn discover_sitemaps(&mut self, origin: &str, listed: &[Url]) {
+        if !self.config.sitemaps || !self.sitemap_origins.insert(origin.to_owned()) {
+            return;
+        }
+        // Opaque origins cannot be parsed, and have no sitemap to look for.
+        if let Ok(sitemap) = Url::parse(origin).and_then(|origin| origin.join("/sitemap.xml")) {
+            self.queue_sitemap(sitemap);
+        }
+        for url in listed {
+            self.queue_sitemap(url.clone());
+        }
+    }
+
+    /// Queues the sitemap at `url` to be requested, unless it has been queued
+    /// before or the crawl has already queued `MAX_SITEMAPS` sitemaps.
+    fn queue_sitemap(&mut self, url: Url) {
+        if self.sitemaps.len() < MAX_SITEMAPS && self.sitemaps.insert(url.clone()) {
+            self.sitemap_queue.push_back(url);
+        }
+    }
+
+    /// Returns a future requesting the sitemap at `url`. Redirects are
+    /// followed, as for `robots.txt`, and sitemaps larger than the protocol
+    /// allows are not read.
+    fn request_sitemap(&self, url: Url) -> BoxFuture<'static, Fetched> {
+        let request = self.get(url.clone());
+        let config = self.config.clone();
+        let downloaded = self.downloaded.clone();
+        Box::pin(async move {
+            let body = match request.await.0 {
+                Ok(Some(file)) if file.response.status.is_success() => {
+                    let read = |response| async move {
+                        let body = read_body(response, Some(MAX_SITEMAP_SIZE), &downloaded);
+                        body.await.map(|body| (body, false))
+                    };
+                    file.read(&config, read).await.0.ok()
+                }
+                Ok(Some(file)) => {
+                    let _ = file.skip(&config).await;
+                    None
+                }
+                _ => None,
+            };
+            Fetched::Sitemap(body)
+        })
+    }
+
+    /// Parses the sitemap `body`. Pages it lists are added to the to-visit
+    /// queue at depth `0`, and sitemaps it lists are queued to be requested,
+    /// if they should be followed.
+    fn push_sitemap_links(&mut self, body: &[u8]) {
+        match Sitemap::parse(body) {
+            Some(Sitemap::UrlSet(urls)) => {
+                for url in urls {
+                    if self.config.should_follow(&self.seed, &url) {
+                        let _ = self.add_url_to_queue(url, 0, None);
+                    }
+                }
+            }
+            Some(Sitemap::Index(urls)) => {
+                for url in urls {
+                    if self.config.should_follow(&self.seed, &url) {
+                        self.queue_sitemap(url);
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Given a queued URL, `document` and the links found in it, `links`,
+    /// appends those the configuration says should be followed to the
+    /// to-visit queue one level deeper than `document` when applicable.
+    ///
+    /// When the configuration respects `nofollow`, links with
+    /// `rel="nofollow"` are not added.
+    ///
+    /// Nothing is added if that level is beyond the configured `max_depth`.
+    fn push_document_links(&mut self, document: &QueuedUrl, links: &[Link]) {
+        let depth = document.depth + 1;
+        if let Some(max_depth) = self.config.max_depth {
+            if depth > max_depth {
+                return;
+            }
+        }
+        let urls = links
+            .iter()
+            .filter(|link| !(self.config.respect_nofollow && link.nofollow))
+            .map(|link| &link.url)
+            // Ensure URL is within our configured scope.
+            .filter(|url| self.config.should_follow(&self.seed, url))
+            .cloned()
+            .collect::<Vec<_>>();
 
         // Take our URL collection and insert it into the queue.
         for url in urls {
-            let _ = self.add_url_to_queue(url);
+            let _ = self.add_url_to_queue(url, depth, Some(document.url.clone()));
+        }
+    }
+
+    /// Returns the first of the configured budgets that has been used up, if
+    /// any.
+    fn spent_budget(&self) -> Option<Budget> {
+        let config = &self.config;
+        if config.max_pages.is_some_and(|max| self.dispatched >= max) {
+            return Some(Budget::Pages);
+        }
+        let downloaded = self.downloaded.load(AtomicOrdering::Relaxed);
+        if config.max_bytes.is_some_and(|max| downloaded >= max) {
+            return Some(Budget::Bytes);
+        }
+        if config
+            .max_duration
+            .is_some_and(|max| self.started.elapsed() >= max)
+        {
+            return Some(Budget::Duration);
+        }
+        None
+    }
+
+    /// Records that `budget` ended the crawl, unless another already has.
+    fn exhaust(&self, budget: Budget) {
+        self.exhausted.lock().unwrap().get_or_insert(budget);
+    }
+
+    /// Moves sitemaps, then URLs from the to-visit queue, into flight until
+    /// either both queues are drained, the concurrency limit is reached, or a
+    /// budget is used up. URLs that have already been visited are discarded,
+    /// and sitemaps and URLs are checked against `robots.txt` first when
+    /// configured.
+    ///
+    /// Redirects are followed the same way, other than being part of a page
+    /// already counted against the budgets. A redirect to a URL that has
+    /// already been visited is not followed, and its page is recorded as
+    /// visited with the redirect as its response, unless it leads to the
+    /// page's own canonical URL.
+    ///
+    /// A budget is only recorded as ending the crawl when there was still an
+    /// unvisited URL left to request.
+    fn fill_in_flight(&mut self) {
+        let concurrency = self.config.concurrency.max(1);
+        while self.in_flight.len() < concurrency {
+            let url = match self.sitemap_queue.pop_front() {
+                None => break,
+                Some(url) => url,
+            };
+            if let Some(budget) = self.spent_budget() {
+                self.exhaust(budget);
+                self.sitemap_queue.push_front(url);
+                return;
+            }
+            if let Some(url) = self.check_sitemap_robots(url) {
+                self.remind_crawl_delay(&url);
+                self.in_flight.push(self.request_sitemap(url));
+            }
+        }
+        while self.in_flight.len() < concurrency {
+            let queued = match self.to_visit.pop() {
+                None => return,
+                Some(queued) => queued,
+            };
+            // Where a URL redirects to is canonicalized only once it is to be
+            // requested, rather than when queued.
+            let target = match &queued.redirected {
+                Some(_) => self.config.canonicalizer.canonicalize(queued.target()),
+                None => queued.url.clone(),
+            };
+            let redirected = queued.redirected.is_some();
+            // A redirect between forms of the same canonical URL, such as one
+            // adding a trailing slash, is followed. Loops are caught by the
+            // redirects recorded.
+            let same_page = redirected && target == queued.url;
+            if self.visited.contains(&target) && !same_page {
+                if redirected {
+                    let result = Self::result_without_document(queued, CrawlOutcome::Visited);
+                    self.ready.push_back(result);
+                }
+                continue;
+            }
+            if !redirected {
+                if let Some(budget) = self.spent_budget() {
+                    self.exhaust(budget);
+                    self.to_visit.push(queued);
+                    return;
+                }
+            }
+            if !self.config.respect_robots_txt {
+                // Without `robots.txt` to list them, sitemaps are looked for
+                // as soon as an origin is seen.
+                let origin = target.origin().ascii_serialization();
+                self.discover_sitemaps(&origin, &[]);
+            }
+            let queued = match self.check_robots(queued) {
+                None => continue,
+                Some(queued) => queued,
+            };
+            self.visited.insert(target);
+            self.remind_crawl_delay(queued.target());
+            let request = self.document_for_url(&queued);
+            self.in_flight.push(Box::pin(async move {
+                let (_, response, body) = request.await;
+                Fetched::Document(queued, response, body)
+            }));
+            if !redirected {
+                self.dispatched += 1;
+            }
+        }
+    }
+
+    /// Returns the result of `queued` without a document, with `outcome`. Its
+    /// response is the last response for it, if it redirected.
+    fn result_without_document(queued: QueuedUrl, outcome: CrawlOutcome) -> CrawlResult<T> {
+        CrawlResult {
+            url: queued.url,
+            original_url: queued.original,
+            depth: queued.depth,
+            referrer: queued.referrer,
+            outcome,
+            response: queued.redirected.map(|response| *response),
+            links: Vec::new(),
+            noindex: false,
+            canonical: None,
+            data: None,
         }
     }
 
-    /// Consumes the `CrawlState` and returns a tuple containing a
-    /// `CrawlResult` for an arbitrary URL in the queue, and a new `CrawlState`.
+    /// Consumes the `CrawlState` and returns a tuple containing the next
+    /// `CrawlResult`, and a new `CrawlState`. Skipped URLs are returned first,
+    /// followed by whichever in-flight request completes first.
     ///
-    /// When there are no URLs to visit `None` is returned.
+    /// When there are no URLs to visit and no requests in flight, or the time
+    /// budget has run out, `None` is returned.
     ///
     /// The produced `CrawlState` marks the returned `CrawlResult` URL as
     /// visited, and has all applicable domain links from the given page added
     /// to the visit queue.
-    async fn pop_document(mut self) -> Option<(CrawlResult, Self)> {
+    async fn pop_document(mut self) -> Option<(CrawlResult<T>, Self)> {
         loop {
-            // Grab some arbitrary URL from the queue.
-            let p = self.to_visit.pop();
-            match p {
-                None => {
-                    // End the crawl for good. Our to-visit queue has been
-                    // fully consumed.
-                    return None;
-                }
-                Some(url) => {
-                    // If the URL has been visited before we just loop round to
-                    // the next URL. Otherwise we index and grab the document.
-                    let is_unvisited = self.visited.insert(url.clone());
-                    if is_unvisited {
-                        // The newly produced document may contain links to
-                        // additional URLs to index within this repo. Add these
-                        // to our to-visit queue if applicable.
-                        if let Ok(doc) = self.document_for_url(&url).await {
-                            self.push_document_links(&url, &doc);
+            self.fill_in_flight();
+            if let Some(result) = self.ready.pop_front() {
+                return Some((result, self));
+            }
+
+            // An empty in-flight collection yields `None` immediately, ending
+            // the crawl for good. Our to-visit queue has been fully consumed.
+            // Requests still in flight when the time budget runs out are
+            // abandoned.
+            let next = match self.config.max_duration {
+                None => self.in_flight.next().await,
+                Some(max_duration) => {
+                    let deadline = self.started + max_duration;
+                    match tokio::time::timeout_at(deadline.into(), self.in_flight.next()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            self.exhaust(Budget::Duration);
+                            return None;
                         }
-                        return Some((CrawlResult { url }, self));
                     }
                 }
+            };
+            match next? {
+                Fetched::Robots(origin, robots, archived) => {
+                    self.set_robots(origin, robots, archived)
+                }
+                Fetched::Sitemap(body) => {
+                    if let Some(body) = body {
+                        self.push_sitemap_links(&body);
+                    }
+                }
+                Fetched::Document(queued, Some(response), Ok(Body::Redirect)) => {
+                    let redirected = Some(Box::new(response));
+                    self.to_visit.push(QueuedUrl {
+                        redirected,
+                        ..queued
+                    });
+                }
+                Fetched::Document(queued, response, body) => {
+                    // The newly produced document may contain links to
+                    // additional URLs to index within this repo. Add these to
+                    // our to-visit queue if applicable.
+                    let mut links = Vec::new();
+                    let mut canonical = None;
+                    let mut data = None;
+                    let agent = &self.config.robots_agent;
+                    let mut directives = match &response {
+                        Some(response) => RobotsDirectives::from_headers(&response.headers, agent),
+                        None => RobotsDirectives::default(),
+                    };
+                    let final_url = match &response {
+                        Some(response) => response.final_url.clone(),
+                        None => queued.url.clone(),
+                    };
+                    let canonicalizer = &self.config.canonicalizer;
+                    let outcome = match body {
+                        Ok(Body::Html(body)) => {
+                            // Where the document redirected to has now been
+                            // visited too.
+                            self.visited.insert(canonicalizer.canonicalize(&final_url));
+                            // Relative links are resolved against where the
+                            // document was served from, after any redirects,
+                            // unless it declares a `<base href>`.
+                            let doc = Html::parse_document(body.as_str());
+                            links = extract_links(&doc, &final_url, &self.config.link_sources);
+                            canonical = canonical_url(&doc, &final_url)
+                                .map(|url| canonicalizer.canonicalize(&url));
+                            directives.add_meta(&doc, agent);
+                            if let Some(response) = &response {
+                                data = Some(self.extractor.extract(&queued.url, &doc, response));
+                            }
+                            if !(self.config.respect_nofollow && directives.nofollow) {
+                                self.push_document_links(&queued, &links);
+                            }
+                            CrawlOutcome::Visited
+                        }
+                        Ok(Body::Css(body)) => {
+                            self.visited.insert(canonicalizer.canonicalize(&final_url));
+                            // Relative links in a stylesheet are resolved
+                            // against the stylesheet itself.
+                            links = css_links(&body, &final_url, &self.config.link_sources);
+                            if !(self.config.respect_nofollow && directives.nofollow) {
+                                self.push_document_links(&queued, &links);
+                            }
+                            CrawlOutcome::Visited
+                        }
+                        // Redirects always come with their response, so are
+                        // followed above.
+                        Ok(Body::NotHtml) | Ok(Body::Redirect) => CrawlOutcome::Visited,
+                        Ok(Body::RedirectOutOfScope) => {
+                            CrawlOutcome::Skipped(SkipReason::RedirectOutOfScope)
+                        }
+                        Err(e) => CrawlOutcome::Failed(e),
+                    };
+                    let result = CrawlResult {
+                        url: queued.url,
+                        original_url: queued.original,
+                        depth: queued.depth,
+                        referrer: queued.referrer,
+                        outcome,
+                        response,
+                        links,
+                        noindex: directives.noindex,
+                        canonical,
+                        data,
+                    };
+                    return Some((result, self));
+                }
             }
         }
     }
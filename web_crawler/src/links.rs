@@ -0,0 +1,510 @@
+//! Extraction of the links in an HTML document.
+use crate::css::css_links;
+use reqwest::Url;
+use scraper::html::Html;
+use scraper::selector::Selector;
+
+/// The element and attribute a link was found in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LinkSource {
+    /// `<a href>`.
+    AnchorHref,
+    /// `<link href>`, except `<link rel="canonical">`, which declares the
+    /// document's canonical URL rather than linking to another page.
+    LinkHref,
+    /// `<area href>`.
+    AreaHref,
+    /// `<iframe src>`.
+    IframeSrc,
+    /// `<meta http-equiv="refresh" content="0; url=...">`.
+    MetaRefresh,
+    /// `<img src>`.
+    ImgSrc,
+    /// `<img srcset>`, one link per candidate image.
+    ImgSrcset,
+    /// `<source src>`, within `<audio>` and `<video>`.
+    SourceSrc,
+    /// `<source srcset>`, within `<picture>`.
+    SourceSrcset,
+    /// `<script src>`.
+    ScriptSrc,
+    /// `<video poster>`.
+    VideoPoster,
+    /// `<form action>`.
+    FormAction,
+    /// `url()` in a stylesheet or `<style>` element, such as a background
+    /// image or font. Stylesheets are only read for links when a CSS source
+    /// is configured.
+    CssUrl,
+    /// `@import` in a stylesheet or `<style>` element.
+    CssImport,
+}
+
+impl LinkSource {
+    /// Every source links are extracted from.
+    pub const ALL: &'static [LinkSource] = &[
+        LinkSource::AnchorHref,
+        LinkSource::LinkHref,
+        LinkSource::AreaHref,
+        LinkSource::IframeSrc,
+        LinkSource::MetaRefresh,
+        LinkSource::ImgSrc,
+        LinkSource::ImgSrcset,
+        LinkSource::SourceSrc,
+        LinkSource::SourceSrcset,
+        LinkSource::ScriptSrc,
+        LinkSource::VideoPoster,
+        LinkSource::FormAction,
+        LinkSource::CssUrl,
+        LinkSource::CssImport,
+    ];
+
+    /// The sources links are extracted from unless otherwise specified: `<a
+    /// href>` and `<link href>`. Every other source, including those that also
+    /// lead to other pages, such as `LinkSource::AreaHref`,
+    /// `LinkSource::IframeSrc` and `LinkSource::MetaRefresh`, is opt-in.
+    pub const DEFAULT: &'static [LinkSource] = &[LinkSource::AnchorHref, LinkSource::LinkHref];
+
+    /// The sources of links that lead to other pages, rather than to the
+    /// resources a page is made from: `LinkSource::DEFAULT`, along with
+    /// `<area href>`, `<iframe src>` and `<meta http-equiv="refresh">`.
+    pub const PAGES: &'static [LinkSource] = &[
+        LinkSource::AnchorHref,
+        LinkSource::LinkHref,
+        LinkSource::AreaHref,
+        LinkSource::IframeSrc,
+        LinkSource::MetaRefresh,
+    ];
+
+    /// Returns `true` if this source's links are found in CSS rather than in
+    /// an attribute.
+    pub fn is_css(self) -> bool {
+        matches!(self, LinkSource::CssUrl | LinkSource::CssImport)
+    }
+
+    /// The CSS selector for the elements holding this source's links, and the
+    /// attribute holding them. CSS sources have none.
+    fn selector(self) -> Option<(&'static str, &'static str)> {
+        Some(match self {
+            LinkSource::AnchorHref => ("a[href]", "href"),
+            LinkSource::LinkHref => ("link[href]", "href"),
+            LinkSource::AreaHref => ("area[href]", "href"),
+            LinkSource::IframeSrc => ("iframe[src]", "src"),
+            LinkSource::MetaRefresh => ("meta[http-equiv][content]", "content"),
+            LinkSource::ImgSrc => ("img[src]", "src"),
+            LinkSource::ImgSrcset => ("img[srcset]", "srcset"),
+            LinkSource::SourceSrc => ("source[src]", "src"),
+            LinkSource::SourceSrcset => ("source[srcset]", "srcset"),
+            LinkSource::ScriptSrc => ("script[src]", "src"),
+            LinkSource::VideoPoster => ("video[poster]", "poster"),
+            LinkSource::FormAction => ("form[action]", "action"),
+            LinkSource::CssUrl | LinkSource::CssImport => return None,
+        })
+    }
+}
+
+/// A link found in a document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    /// The URL linked to, resolved against the document's base URL.
+    pub url: Url,
+    /// Where in the document the link was found.
+    pub source: LinkSource,
+    /// Whether the link's element has `rel="nofollow"`.
+    pub nofollow: bool,
+    /// The text of the link, with whitespace collapsed: the content of an
+    /// `<a>`, or the `alt` of an `<area>`. Other links, and links without
+    /// text, have none.
+    pub text: Option<String>,
+}
+
+/// Returns the links in `html`, served from `url`, found in any of `sources`,
+/// in document order for each source, followed by those in `<style>`
+/// elements. Relative links are resolved against the document's base URL,
+/// and links that are not valid URLs are ignored.
+pub(crate) fn extract_links(html: &Html, url: &Url, sources: &[LinkSource]) -> Vec<Link> {
+    let base = document_base(html, url);
+    let mut links = Vec::new();
+    for &source in sources {
+        let (selector, attribute) = match source.selector() {
+            Some(selector) => selector,
+            None => continue,
+        };
+        // Each selector is a constant known to be valid.
+        let selector = Selector::parse(selector).unwrap();
+        for element_ref in html.select(&selector) {
+            let element = element_ref.value();
+            // Checked by the selector: the attribute is present.
+            let value = element.attr(attribute).unwrap();
+            if source == LinkSource::LinkHref && has_rel(element.attr("rel"), "canonical") {
+                continue;
+            }
+            let nofollow = has_rel(element.attr("rel"), "nofollow");
+            let text = match source {
+                LinkSource::AnchorHref => collapse_whitespace(element_ref.text()),
+                LinkSource::AreaHref => collapse_whitespace(element.attr("alt")),
+                _ => None,
+            };
+            let raw_urls = match source {
+                LinkSource::MetaRefresh => {
+                    let refresh = element
+                        .attr("http-equiv")
+                        .is_some_and(|v| v.eq_ignore_ascii_case("refresh"));
+                    refresh_url(value).filter(|_| refresh).into_iter().collect()
+                }
+                LinkSource::ImgSrcset | LinkSource::SourceSrcset => srcset_urls(value),
+                _ => vec![value],
+            };
+            links.extend(raw_urls.into_iter().filter_map(|raw_url| {
+                let url = base.join(raw_url.trim()).ok()?;
+                Some(Link {
+                    url,
+                    source,
+                    nofollow,
+                    text: text.clone(),
+                })
+            }));
+        }
+    }
+    if sources.iter().any(|source| source.is_css()) {
+        let selector = Selector::parse("style").unwrap();
+        for style in html.select(&selector) {
+            let css: String = style.text().collect();
+            links.extend(css_links(&css, &base, sources));
+        }
+    }
+    links
+}
+
+/// Returns the canonical URL `html`, served from `url`, declares for itself.
+/// This is the `href` of its first `<link rel="canonical">` element, resolved
+/// against the document's base URL, if it has one that is a valid URL.
+pub(crate) fn canonical_url(html: &Html, url: &Url) -> Option<Url> {
+    let selector = Selector::parse("link[rel][href]").unwrap();
+    let link = html
+        .select(&selector)
+        .find(|link| has_rel(link.value().attr("rel"), "canonical"))?;
+    let base = document_base(html, url);
+    // Checked by the selector: the attribute is present.
+    base.join(link.value().attr("href").unwrap().trim()).ok()
+}
+
+/// Joins the pieces of text in `text`, collapsing each run of whitespace to a
+/// single space. Returns `None` if there is no text left.
+pub(crate) fn collapse_whitespace<'a>(text: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let mut collapsed = String::new();
+    for word in text.into_iter().flat_map(str::split_whitespace) {
+        if !collapsed.is_empty() {
+            collapsed.push(' ');
+        }
+        collapsed.push_str(word);
+    }
+    Some(collapsed).filter(|text| !text.is_empty())
+}
+
+/// Returns `true` if the `rel` attribute value `rel` contains `keyword`.
+fn has_rel(rel: Option<&str>, keyword: &str) -> bool {
+    rel.is_some_and(|rel| {
+        rel.split_ascii_whitespace()
+            .any(|t| t.eq_ignore_ascii_case(keyword))
+    })
+}
+
+/// Returns the base URL of `html`, served from `url`. This is the `href` of
+/// its first `<base href>` element, resolved against `url`, or `url` itself
+/// if there is none.
+fn document_base(html: &Html, url: &Url) -> Url {
+    let selector = Selector::parse("base[href]").unwrap();
+    html.select(&selector)
+        .next()
+        .and_then(|base| url.join(base.value().attr("href")?.trim()).ok())
+        // Only URLs that can have paths joined onto them make a base.
+        .filter(|base| !base.cannot_be_a_base())
+        .unwrap_or_else(|| url.clone())
+}
+
+/// Returns the URL of the `content` of a `<meta http-equiv="refresh">`, such
+/// as `5; url=/next.html`, if it has one.
+fn refresh_url(content: &str) -> Option<&str> {
+    let (_, rest) = content.split_once([';', ','])?;
+    let rest = rest.trim_start();
+    let rest = match rest.get(..3) {
+        Some(key) if key.eq_ignore_ascii_case("url") => rest[3..].trim_start().strip_prefix('=')?,
+        _ => rest,
+    };
+    let rest = rest.trim();
+    let url = match rest.chars().next()? {
+        quote @ ('"' | '\'') => rest[1..].split(quote).next()?,
+        _ => rest,
+    };
+    Some(url)
+}
+
+/// Returns the URLs of the candidate images in `srcset`, such as
+/// `small.jpg 480w, large.jpg 1080w`, following the HTML algorithm for parsing
+/// a srcset attribute: each URL runs up to the next whitespace, so it may
+/// contain commas, and is followed by a descriptor running up to the next
+/// comma outside parentheses. Trailing commas are stripped from each URL.
+fn srcset_urls(srcset: &str) -> Vec<&str> {
+    let is_space = |c: char| c.is_ascii_whitespace();
+    let mut urls = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| is_space(c) || c == ',');
+        if rest.is_empty() {
+            return urls;
+        }
+        let end = rest.find(is_space).unwrap_or(rest.len());
+        let (url, after) = rest.split_at(end);
+        rest = after;
+        let url = url.trim_end_matches(',');
+        if !url.is_empty() {
+            urls.push(url);
+        }
+        if url.len() < end {
+            // The URL ended with a comma, so it has no descriptor.
+            continue;
+        }
+        // Skip the descriptor, up to the comma that ends the candidate.
+        let mut depth = 0usize;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| match c {
+                '(' => {
+                    depth += 1;
+                    false
+                }
+                ')' => {
+                    depth = depth.saturating_sub(1);
+                    false
+                }
+                ',' => depth == 0,
+                _ => false,
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        rest = &rest[end..];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PAGE: &str = r#"<html><head>
+        <meta http-equiv="Refresh" content="5; URL='/next.html'">
+        <meta http-equiv="content-type" content="text/html">
+        <link rel="stylesheet" href="style.css">
+        <script src="/app.js"></script>
+        </head><body>
+        <a href="page.html">Page</a>
+        <img src="a.png" srcset="a-1x.png 1x, a-2x.png 2x">
+        <picture><source srcset="b.webp"></picture>
+        <video poster="poster.jpg"><source src="movie.mp4"></video>
+        <iframe src="https://www.google.com/embed"></iframe>
+        <map><area href="/area.html"></map>
+        <form action="/search"></form>
+        <style>@import "print.css"; body { background: url(/bg.png) }</style>
+        </body></html>"#;
+
+    fn links(sources: &[LinkSource]) -> Vec<(String, LinkSource)> {
+        let base = Url::parse("https://www.enhance.com/docs/index.html").unwrap();
+        extract_links(&Html::parse_document(PAGE), &base, sources)
+            .into_iter()
+            .map(|link| (link.url.to_string(), link.source))
+            .collect()
+    }
+
+    #[test]
+    fn test_default_sources() {
+        assert_eq!(
+            links(LinkSource::DEFAULT),
+            vec![
+                (
+                    "https://www.enhance.com/docs/page.html".to_owned(),
+                    LinkSource::AnchorHref
+                ),
+                (
+                    "https://www.enhance.com/docs/style.css".to_owned(),
+                    LinkSource::LinkHref
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_page_sources() {
+        assert_eq!(
+            links(LinkSource::PAGES),
+            vec![
+                (
+                    "https://www.enhance.com/docs/page.html".to_owned(),
+                    LinkSource::AnchorHref
+                ),
+                (
+                    "https://www.enhance.com/docs/style.css".to_owned(),
+                    LinkSource::LinkHref
+                ),
+                (
+                    "https://www.enhance.com/area.html".to_owned(),
+                    LinkSource::AreaHref
+                ),
+                (
+                    "https://www.google.com/embed".to_owned(),
+                    LinkSource::IframeSrc
+                ),
+                (
+                    "https://www.enhance.com/next.html".to_owned(),
+                    LinkSource::MetaRefresh
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_all_sources() {
+        let links = links(LinkSource::ALL);
+        assert_eq!(links.len(), 15);
+        let srcset: Vec<&str> = links
+            .iter()
+            .filter(|(_, source)| *source == LinkSource::ImgSrcset)
+            .map(|(url, _)| url.as_str())
+            .collect();
+        assert_eq!(
+            srcset,
+            vec![
+                "https://www.enhance.com/docs/a-1x.png",
+                "https://www.enhance.com/docs/a-2x.png"
+            ]
+        );
+        assert!(links.contains(&(
+            "https://www.enhance.com/search".to_owned(),
+            LinkSource::FormAction
+        )));
+        assert_eq!(
+            links[13..],
+            [
+                (
+                    "https://www.enhance.com/docs/print.css".to_owned(),
+                    LinkSource::CssImport
+                ),
+                (
+                    "https://www.enhance.com/bg.png".to_owned(),
+                    LinkSource::CssUrl
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nofollow() {
+        let url = Url::parse("https://www.enhance.com/").unwrap();
+        let html = Html::parse_document(
+            r#"<a href="/a.html" rel="external NoFollow">A</a><a href="/b.html">B</a>"#,
+        );
+        let nofollow: Vec<bool> = extract_links(&html, &url, LinkSource::DEFAULT)
+            .iter()
+            .map(|link| link.nofollow)
+            .collect();
+        assert_eq!(nofollow, vec![true, false]);
+    }
+
+    #[test]
+    fn test_text() {
+        let url = Url::parse("https://www.enhance.com/").unwrap();
+        let html = Html::parse_document(
+            r#"<a href="/a.html">
+                Read <em>more</em>
+            </a><a href="/b.html"><img src="b.png"></a>
+            <map><area href="/c.html" alt="Area C"></map>"#,
+        );
+        let text: Vec<Option<String>> = extract_links(&html, &url, LinkSource::PAGES)
+            .into_iter()
+            .map(|link| link.text)
+            .collect();
+        assert_eq!(
+            text,
+            vec![
+                Some("Read more".to_owned()),
+                None,
+                Some("Area C".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_base_href() {
+        let url = Url::parse("https://www.enhance.com/blog/2021/post.html").unwrap();
+        let html = Html::parse_document(
+            r#"<head><base href="/blog/"><base href="/ignored/"></head>
+            <a href="other.html">Other</a>"#,
+        );
+        let links = extract_links(&html, &url, LinkSource::DEFAULT);
+        assert_eq!(
+            links[0].url.as_str(),
+            "https://www.enhance.com/blog/other.html"
+        );
+
+        let html = Html::parse_document(r#"<base href="data:text/html,"><a href="a.html">A</a>"#);
+        let links = extract_links(&html, &url, LinkSource::DEFAULT);
+        assert_eq!(
+            links[0].url.as_str(),
+            "https://www.enhance.com/blog/2021/a.html"
+        );
+    }
+
+    #[test]
+    fn test_canonical() {
+        let url = Url::parse("https://www.enhance.com/docs/page.html?ref=home").unwrap();
+        let html = Html::parse_document(
+            r#"<head><link rel="stylesheet" href="style.css">
+            <link rel="Canonical" href="/docs/page.html"></head>
+            <a href="other.html">Other</a>"#,
+        );
+        assert_eq!(
+            canonical_url(&html, &url).unwrap().as_str(),
+            "https://www.enhance.com/docs/page.html"
+        );
+        // The canonical declaration is not a link to follow.
+        let links: Vec<String> = extract_links(&html, &url, LinkSource::DEFAULT)
+            .into_iter()
+            .map(|link| link.url.to_string())
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                "https://www.enhance.com/docs/other.html",
+                "https://www.enhance.com/docs/style.css"
+            ]
+        );
+
+        let html = Html::parse_document(r#"<a href="/" rel="canonical">Home</a>"#);
+        assert_eq!(canonical_url(&html, &url), None);
+    }
+
+    #[test]
+    fn test_refresh_url() {
+        assert_eq!(refresh_url("0; url=/a.html"), Some("/a.html"));
+        assert_eq!(refresh_url("0;URL = \"/a.html\""), Some("/a.html"));
+        assert_eq!(refresh_url("0, /a.html"), Some("/a.html"));
+        assert_eq!(refresh_url("30"), None);
+    }
+
+    #[test]
+    fn test_srcset_urls() {
+        assert_eq!(
+            srcset_urls("small.jpg 480w, large.jpg 1080w"),
+            vec!["small.jpg", "large.jpg"]
+        );
+        assert_eq!(
+            srcset_urls("/img/w_100,h_50/a.jpg 1x,/img/w_200,h_100/a.jpg 2x"),
+            vec!["/img/w_100,h_50/a.jpg", "/img/w_200,h_100/a.jpg"]
+        );
+        assert_eq!(srcset_urls("a.jpg,b.jpg 2x"), vec!["a.jpg,b.jpg"]);
+        assert_eq!(srcset_urls("a.jpg, b.jpg"), vec!["a.jpg", "b.jpg"]);
+        assert_eq!(
+            srcset_urls("a.jpg (max-width: 1px, 2px) 1x, b.jpg"),
+            vec!["a.jpg", "b.jpg"]
+        );
+        assert_eq!(srcset_urls(" , "), Vec::<&str>::new());
+    }
+}
@@ -0,0 +1,102 @@
+//! Patterns matched against the path and query of discovered URLs, to include
+//! or exclude them from a crawl.
+use regex::Regex;
+use reqwest::Url;
+
+/// A pattern matched against the path and query of a URL, such as
+/// `/search?q=rust`.
+#[derive(Clone, Debug)]
+pub struct UrlPattern {
+    /// The pattern as given.
+    source: String,
+    /// The pattern compiled to a regular expression.
+    regex: Regex,
+}
+
+impl UrlPattern {
+    /// Creates a pattern from a glob, which must match the whole of the path
+    /// and query.
+    ///
+    /// `**` matches any sequence of characters, and `*` any sequence not
+    /// containing a `/`. Every other character matches itself, including `?`.
+    /// So `/docs/**` matches everything under `/docs/`, and `**?sort=*`
+    /// matches any URL whose query starts with a `sort` parameter.
+    pub fn glob(glob: &str) -> Self {
+        let mut pattern = String::from("^");
+        for (i, part) in glob.split("**").enumerate() {
+            if i > 0 {
+                pattern.push_str(".*");
+            }
+            let literals: Vec<String> = part.split('*').map(regex::escape).collect();
+            pattern.push_str(&literals.join("[^/]*"));
+        }
+        pattern.push('$');
+        Self {
+            source: glob.to_owned(),
+            // Every character of the glob is escaped or replaced by a valid
+            // expression, so compiling cannot fail.
+            regex: Regex::new(&pattern).unwrap(),
+        }
+    }
+
+    /// Creates a pattern from a regular expression, which matches if it is
+    /// found anywhere in the path and query. Use `^` and `$` to anchor it.
+    pub fn regex(regex: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            source: regex.to_owned(),
+            regex: Regex::new(regex)?,
+        })
+    }
+
+    /// The pattern as given.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns `true` if the pattern matches the path and query of `url`.
+    pub fn matches(&self, url: &Url) -> bool {
+        match url.query() {
+            Some(query) => self.regex.is_match(&format!("{}?{}", url.path(), query)),
+            None => self.regex.is_match(url.path()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::url;
+
+    #[test]
+    fn test_glob() {
+        let docs = UrlPattern::glob("/docs/**");
+        assert!(docs.matches(&url("/docs/a/b.html")));
+        assert!(docs.matches(&url("/docs/a.html?page=2")));
+        assert!(!docs.matches(&url("/blog/docs/a.html")));
+
+        let star = UrlPattern::glob("/*.html");
+        assert!(star.matches(&url("/a.html")));
+        assert!(!star.matches(&url("/a/b.html")));
+
+        let logout = UrlPattern::glob("/logout");
+        assert!(logout.matches(&url("/logout")));
+        assert!(!logout.matches(&url("/logout/now")));
+    }
+
+    #[test]
+    fn test_glob_query() {
+        let sort = UrlPattern::glob("**?sort=*");
+        assert!(sort.matches(&url("/products?sort=price")));
+        assert!(!sort.matches(&url("/products")));
+        // Special characters are literal.
+        assert!(!UrlPattern::glob("/a.b").matches(&url("/axb")));
+    }
+
+    #[test]
+    fn test_regex() {
+        let sort = UrlPattern::regex("[?&]sort=").unwrap();
+        assert!(sort.matches(&url("/products?page=2&sort=price")));
+        assert!(!sort.matches(&url("/sort=price")));
+        assert!(UrlPattern::regex("(").is_err());
+    }
+}
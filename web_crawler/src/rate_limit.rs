@@ -0,0 +1,487 @@
+//! Per-host politeness limits, shared between crawls.
+use crate::{Error, FetchRequest, FetchResponse, Fetcher};
+use futures::future::BoxFuture;
+use futures::stream::StreamExt;
+use reqwest::{Client, Url};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits on how hard any single host is hit.
+///
+/// The default places no limits at all, unlike `DEFAULT_HOST_LIMITS`, which
+/// a `RateLimiter` applies when it is not given any.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HostLimits {
+    /// The maximum number of requests started per second, if any.
+    pub requests_per_second: Option<f64>,
+    /// The minimum time between the start of consecutive requests.
+    pub min_delay: Duration,
+    /// The maximum number of requests in progress at once, if any.
+    pub max_connections: Option<usize>,
+}
+
+/// The limits of a `RateLimiter` that is not given any: at most two requests
+/// to a host at once, started at least 100 milliseconds apart.
+pub const DEFAULT_HOST_LIMITS: HostLimits = HostLimits {
+    requests_per_second: None,
+    min_delay: Duration::from_millis(100),
+    max_connections: Some(2),
+};
+
+/// How long a host goes without a request before the limiter forgets it,
+/// unless configured otherwise.
+const IDLE_HOST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The longest time kept between requests to a host, whether asked for by
+/// `HostLimits` or a `Crawl-delay`. Longer limits and delays are cut down to
+/// this.
+pub const MAX_HOST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What the limiter knows of a single host.
+struct HostState {
+    /// The earliest time the next request may start.
+    next_request: Instant,
+    /// The `Crawl-delay` the host asked for, if any.
+    crawl_delay: Option<Duration>,
+    /// Permits for requests in progress, when connections are limited.
+    connections: Option<Arc<Semaphore>>,
+}
+
+impl HostState {
+    /// Returns `true` if no request to the host has been due for
+    /// `idle_timeout`, and none is in progress or waiting.
+    fn is_idle(&self, now: Instant, idle_timeout: Duration) -> bool {
+        // Requests in progress or waiting hold a clone of the semaphore.
+        let in_use = self
+            .connections
+            .as_ref()
+            .is_some_and(|connections| Arc::strong_count(connections) > 1);
+        !in_use && now >= self.next_request + idle_timeout
+    }
+}
+
+/// What the limiter knows of every host.
+struct Hosts {
+    /// The state of each host seen recently, keyed by host name.
+    states: HashMap<String, HostState>,
+    /// When idle hosts were last forgotten.
+    pruned: Instant,
+}
+
+/// Spaces out requests to each host according to `HostLimits`, and any
+/// `Crawl-delay` the host asks for.
+///
+/// Clones share their state, so every request waiting on a clone of the same
+/// limiter is limited together. Crawls use the limiter of the
+/// `RateLimitedFetcher` they make requests with.
+///
+/// Hosts that go a minute without a request are forgotten, along with any
+/// `Crawl-delay` they asked for, so that a long-lived limiter does not grow
+/// with every host it has seen. Crawls give the delay again as they go on.
+///
+/// However large the limits and delays, requests to a host are never more
+/// than `MAX_HOST_INTERVAL` apart.
+#[derive(Clone)]
+pub struct RateLimiter {
+    /// The limits applied to every host.
+    limits: Arc<HostLimits>,
+    /// The state of each host seen recently.
+    hosts: Arc<Mutex<Hosts>>,
+    /// How long a host goes without a request before it is forgotten.
+    idle_timeout: Duration,
+}
+
+/// Creates a limiter applying `DEFAULT_HOST_LIMITS` to every host.
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_HOST_LIMITS)
+    }
+}
+
+/// Permission to make a request to a host. The connection it represents is
+/// released when the permit is dropped.
+pub struct HostPermit {
+    /// The connection slot held, when connections are limited.
+    _connection: Option<OwnedSemaphorePermit>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter applying `limits` to every host.
+    pub fn new(limits: HostLimits) -> Self {
+        Self {
+            limits: Arc::new(limits),
+            hosts: Arc::new(Mutex::new(Hosts {
+                states: HashMap::new(),
+                pruned: Instant::now(),
+            })),
+            idle_timeout: IDLE_HOST_TIMEOUT,
+        }
+    }
+
+    /// The limits applied to every host.
+    pub fn limits(&self) -> &HostLimits {
+        &self.limits
+    }
+
+    /// Records that the host of `url` asked for at least `delay` between
+    /// requests. This only ever lengthens the time between requests.
+    ///
+    /// Delays are kept per host, while `robots.txt` is per origin, so the
+    /// longest delay given for a host is kept: its `http` and `https` origins
+    /// are both limited by the longer of their delays. The delay is forgotten
+    /// along with the host once it is idle.
+    pub fn set_crawl_delay(&self, url: &Url, delay: Duration) {
+        let mut hosts = self.hosts();
+        let crawl_delay = &mut self.host_state(&mut hosts, url).crawl_delay;
+        *crawl_delay = (*crawl_delay).max(Some(delay));
+    }
+
+    /// Waits until a request may be made to the host of `url`, returning a
+    /// permit to hold for as long as the request is in progress.
+    pub async fn acquire(&self, url: &Url) -> HostPermit {
+        // Wait for a connection first, so that a request's start time is
+        // reserved only once it can actually be made.
+        let connections = {
+            let mut hosts = self.hosts();
+            self.host_state(&mut hosts, url).connections.clone()
+        };
+        let connection = match connections {
+            // The semaphore is never closed, so acquiring cannot fail.
+            Some(connections) => Some(connections.acquire_owned().await.unwrap()),
+            None => None,
+        };
+
+        let slot = {
+            let mut hosts = self.hosts();
+            let state = self.host_state(&mut hosts, url);
+            let interval = self.interval(state.crawl_delay);
+            let slot = state.next_request.max(Instant::now());
+            state.next_request = slot.checked_add(interval).unwrap_or(slot);
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+
+        HostPermit {
+            _connection: connection,
+        }
+    }
+
+    /// The minimum time between requests to a host that asked for
+    /// `crawl_delay`, at most `MAX_HOST_INTERVAL`.
+    fn interval(&self, crawl_delay: Option<Duration>) -> Duration {
+        let rate = self
+            .limits
+            .requests_per_second
+            .filter(|r| r.is_finite() && *r > 0.0)
+            .map_or(Duration::from_secs(0), |r| {
+                Duration::try_from_secs_f64(1.0 / r).unwrap_or(MAX_HOST_INTERVAL)
+            });
+        rate.max(self.limits.min_delay)
+            .max(crawl_delay.unwrap_or_default())
+            .min(MAX_HOST_INTERVAL)
+    }
+
+    /// Locks the state of every host. The state is consistent between
+    /// statements, so a lock poisoned by a panic elsewhere is still used.
+    fn hosts(&self) -> MutexGuard<'_, Hosts> {
+        self.hosts.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the state of the host of `url` in `hosts`, creating it if the
+    /// host has not been seen recently.
+    ///
+    /// Idle hosts are forgotten first, at most once every `idle_timeout`.
+    fn host_state<'a>(&self, hosts: &'a mut Hosts, url: &Url) -> &'a mut HostState {
+        let now = Instant::now();
+        if now >= hosts.pruned + self.idle_timeout {
+            let idle_timeout = self.idle_timeout;
+            hosts
+                .states
+                .retain(|_, state| !state.is_idle(now, idle_timeout));
+            hosts.pruned = now;
+        }
+        let host = url.host_str().unwrap_or_default().to_owned();
+        let limits = &self.limits;
+        hosts.states.entry(host).or_insert_with(|| HostState {
+            next_request: Instant::now(),
+            crawl_delay: None,
+            connections: limits
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max.max(1)))),
+        })
+    }
+}
+
+/// A `Fetcher` that waits on a `RateLimiter` before passing each request on
+/// to another fetcher, such as a `reqwest::Client`.
+///
+/// Clones share the limiter, so every crawl made with a clone of the same
+/// fetcher is limited together, along with the `Crawl-delay` each host asks
+/// for. This is how crawls sharing a client share host limits: wrap the
+/// client once, and hand out clones of the wrapper. `CrawlerBuilder` wraps
+/// the fetcher of each `Crawler` it builds in one of these.
+///
+/// A request holds its host's connection, when connections are limited,
+/// until its response's body is dropped.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use web_crawler_lib::{unique_url_count_with_client, HostLimits, RateLimitedFetcher, RateLimiter};
+/// use reqwest::{Client, Url};
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// pub async fn main() {
+///     let limiter = RateLimiter::new(HostLimits {
+///         min_delay: Duration::from_millis(250),
+///         ..HostLimits::default()
+///     });
+///     let client = RateLimitedFetcher::new(Client::new(), limiter);
+///     let url = Url::parse("https://www.linuxmint.com/").unwrap();
+///     // Both crawls wait on the same limits.
+///     let (first, second) = futures::join!(
+///         unique_url_count_with_client(client.clone(), url.clone()),
+///         unique_url_count_with_client(client, url),
+///     );
+///     println!("{} and {} pages", first, second);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct RateLimitedFetcher {
+    /// The fetcher requests are passed on to.
+    fetcher: Arc<dyn Fetcher>,
+    /// The limiter every request waits on.
+    limiter: RateLimiter,
+}
+
+impl RateLimitedFetcher {
+    /// Creates a fetcher making requests with `fetcher`, each once `limiter`
+    /// allows.
+    pub fn new<F: Fetcher>(fetcher: F, limiter: RateLimiter) -> Self {
+        Self::from_shared(Arc::new(fetcher), limiter)
+    }
+
+    /// Creates a fetcher making requests with the shared `fetcher`, each once
+    /// `limiter` allows.
+    pub(crate) fn from_shared(fetcher: Arc<dyn Fetcher>, limiter: RateLimiter) -> Self {
+        Self { fetcher, limiter }
+    }
+
+    /// The limiter every request waits on.
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+}
+
+/// Wraps `client` with a limiter of its own, applying `DEFAULT_HOST_LIMITS`
+/// and the `Crawl-delay` hosts ask for. The limiter is not shared with other
+/// conversions of the same client.
+impl From<Client> for RateLimitedFetcher {
+    fn from(client: Client) -> Self {
+        Self::new(client, RateLimiter::default())
+    }
+}
+
+impl Fetcher for RateLimitedFetcher {
+    fn fetch(&self, request: FetchRequest) -> BoxFuture<'static, Result<FetchResponse, Error>> {
+        let fetcher = self.fetcher.clone();
+        let limiter = self.limiter.clone();
+        Box::pin(async move {
+            let permit = limiter.acquire(&request.url).await;
+            let mut response = fetcher.fetch(request).await?;
+            // The permit goes with the body, so that it is released once the
+            // body is dropped.
+            response.body = response
+                .body
+                .map(move |chunk| {
+                    let _ = &permit;
+                    chunk
+                })
+                .boxed();
+            Ok(response)
+        })
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        Some(&self.limiter)
+    }
+}
+
+impl fmt::Debug for RateLimitedFetcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitedFetcher")
+            .field("limits", self.limiter.limits())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_min_delay() {
+        let limiter = RateLimiter::new(HostLimits {
+            min_delay: Duration::from_millis(50),
+            ..HostLimits::default()
+        });
+        let start = Instant::now();
+        for _ in 0..3 {
+            let _ = limiter.acquire(&url("https://www.enhance.com/")).await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_hosts_are_independent() {
+        let limiter = RateLimiter::new(HostLimits {
+            requests_per_second: Some(1.0),
+            ..HostLimits::default()
+        });
+        let start = Instant::now();
+        let _ = limiter.acquire(&url("https://www.enhance.com/")).await;
+        let _ = limiter.acquire(&url("https://www.google.com/")).await;
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_crawl_delay() {
+        let limiter = RateLimiter::new(HostLimits::default());
+        let page = url("https://www.enhance.com/");
+        limiter.set_crawl_delay(&page, Duration::from_millis(50));
+
+        // Clones share the delay.
+        let clone = limiter.clone();
+        let start = Instant::now();
+        let _ = limiter.acquire(&page).await;
+        let _ = clone.acquire(&page).await;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_default_limits() {
+        assert_eq!(RateLimiter::default().limits(), &DEFAULT_HOST_LIMITS);
+        let fetcher = RateLimitedFetcher::from(Client::new());
+        assert_eq!(fetcher.limiter().limits(), &DEFAULT_HOST_LIMITS);
+    }
+
+    #[test]
+    fn test_longest_crawl_delay_kept() {
+        let limiter = RateLimiter::default();
+        let delay = |limiter: &RateLimiter| {
+            let mut hosts = limiter.hosts();
+            limiter
+                .host_state(&mut hosts, &url("https://www.enhance.com/"))
+                .crawl_delay
+        };
+        limiter.set_crawl_delay(&url("https://www.enhance.com/"), Duration::from_secs(5));
+        // The http origin of the same host asks for less.
+        limiter.set_crawl_delay(&url("http://www.enhance.com/"), Duration::from_secs(1));
+        assert_eq!(delay(&limiter), Some(Duration::from_secs(5)));
+        limiter.set_crawl_delay(&url("http://www.enhance.com/"), Duration::from_secs(10));
+        assert_eq!(delay(&limiter), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_huge_limits_capped() {
+        let limiter = RateLimiter::new(HostLimits {
+            requests_per_second: Some(1e-300),
+            ..HostLimits::default()
+        });
+        assert_eq!(limiter.interval(None), MAX_HOST_INTERVAL);
+        let limiter = RateLimiter::default();
+        let delay = Duration::from_secs_f64(1e19);
+        assert_eq!(limiter.interval(Some(delay)), MAX_HOST_INTERVAL);
+    }
+
+    #[tokio::test]
+    async fn test_huge_crawl_delay() {
+        let limiter = RateLimiter::default();
+        let page = url("https://www.enhance.com/");
+        limiter.set_crawl_delay(&page, Duration::MAX);
+        let _ = limiter.acquire(&page).await;
+        // The next request waits, but the limiter still works.
+        let wait = Duration::from_millis(20);
+        assert!(tokio::time::timeout(wait, limiter.acquire(&page))
+            .await
+            .is_err());
+        let _ = limiter.acquire(&url("https://www.google.com/")).await;
+    }
+
+    #[tokio::test]
+    async fn test_max_connections() {
+        let limiter = RateLimiter::new(HostLimits {
+            max_connections: Some(1),
+            ..HostLimits::default()
+        });
+        let page = url("https://www.enhance.com/");
+        let permit = limiter.acquire(&page).await;
+        let wait = Duration::from_millis(20);
+        assert!(tokio::time::timeout(wait, limiter.acquire(&page))
+            .await
+            .is_err());
+        drop(permit);
+        assert!(tokio::time::timeout(wait, limiter.acquire(&page))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_fetcher_holds_connection_for_body() {
+        use crate::MemoryFetcher;
+        use reqwest::Method;
+
+        let page = url("https://www.enhance.com/");
+        let fetcher = RateLimitedFetcher::new(
+            MemoryFetcher::new().with_page(page.clone(), "text/html", "<p>Home</p>"),
+            RateLimiter::new(HostLimits {
+                max_connections: Some(1),
+                ..HostLimits::default()
+            }),
+        );
+        assert!(fetcher.rate_limiter().is_some());
+        let request = || fetcher.fetch(FetchRequest::new(Method::GET, page.clone()));
+        let response = request().await.unwrap();
+        let wait = Duration::from_millis(20);
+        assert!(tokio::time::timeout(wait, request()).await.is_err());
+        // Reading the body to its end keeps the connection until the body is
+        // dropped.
+        let mut body = response.body;
+        while body.next().await.is_some() {}
+        assert!(tokio::time::timeout(wait, request()).await.is_err());
+        drop(body);
+        assert!(tokio::time::timeout(wait, request()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_idle_hosts_forgotten() {
+        let limiter = RateLimiter {
+            idle_timeout: Duration::from_millis(20),
+            ..RateLimiter::new(HostLimits {
+                max_connections: Some(1),
+                ..HostLimits::default()
+            })
+        };
+        let busy = url("https://www.enhance.com/");
+        let idle = url("https://www.google.com/");
+        let _permit = limiter.acquire(&busy).await;
+        limiter.set_crawl_delay(&idle, Duration::from_millis(1));
+        assert_eq!(limiter.hosts().states.len(), 2);
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let _ = limiter.acquire(&url("https://www.linuxmint.com/")).await;
+        // The host with a request in progress is kept.
+        let hosts = limiter.hosts();
+        let mut names: Vec<&str> = hosts.states.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["www.enhance.com", "www.linuxmint.com"]);
+    }
+}
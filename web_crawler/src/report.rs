@@ -0,0 +1,125 @@
+//! Reports of the URLs of a crawl that could not be visited, and the pages
+//! linking to them.
+use crate::{Canonicalizer, CrawlResult, Edge, Error, LinkGraph, LinkSource};
+use reqwest::Url;
+use std::collections::HashMap;
+
+/// A URL that failed, along with every page found linking to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BrokenLink {
+    /// The URL that failed, in its canonical form.
+    pub url: Url,
+    /// Why it failed, such as `Error::HttpStatus(StatusCode::NOT_FOUND)`.
+    pub error: Error,
+    /// The links to the URL, in the order found. A page linking to it more
+    /// than once appears once for each link.
+    pub referrers: Vec<Referrer>,
+}
+
+/// A link to a broken URL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Referrer {
+    /// The page the link was found on.
+    pub page: Url,
+    /// The text of the link, if it has any. See `Link::text`.
+    pub text: Option<String>,
+    /// Where in the page the link was found.
+    pub kind: LinkSource,
+}
+
+/// Returns every URL in `results` that failed, with the pages in `results`
+/// that link to it, in the order of `results`. Link targets are canonicalized
+/// with `canonicalizer` to match the URLs of results. This should be the
+/// canonicalizer of the crawl.
+///
+/// A URL fails with an error status, such as `404 Not Found`, or when no
+/// response could be had at all. URLs out of the crawl's scope are never
+/// requested, so never reported.
+pub fn broken_links<'a, I, T>(results: I, canonicalizer: &Canonicalizer) -> Vec<BrokenLink>
+where
+    I: IntoIterator<Item = &'a CrawlResult<T>>,
+    T: 'a,
+{
+    let mut graph = LinkGraph::new();
+    let mut failed = Vec::new();
+    for result in results {
+        graph.add_result(result, canonicalizer);
+        if let Some(error) = result.error() {
+            failed.push((result.url.clone(), error.clone()));
+        }
+    }
+    // The links to each URL, so that each failure's are found at once.
+    let mut inlinks: HashMap<&Url, Vec<&Edge>> = HashMap::new();
+    for edge in graph.edges() {
+        inlinks.entry(&edge.target).or_default().push(edge);
+    }
+    failed
+        .into_iter()
+        .map(|(url, error)| {
+            let referrers = inlinks
+                .get(&url)
+                .into_iter()
+                .flatten()
+                .map(|edge| Referrer {
+                    page: edge.source.clone(),
+                    text: edge.text.clone(),
+                    kind: edge.kind,
+                })
+                .collect();
+            BrokenLink {
+                url,
+                error,
+                referrers,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{result, url};
+    use crate::CrawlOutcome;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_broken_links() {
+        let failed = |path, error| CrawlResult {
+            outcome: CrawlOutcome::Failed(error),
+            ..result(path, &[])
+        };
+        let results = vec![
+            result("/", &["/gone.html#top", "/ok.html"]),
+            failed("/gone.html", Error::HttpStatus(StatusCode::NOT_FOUND)),
+            result("/ok.html", &["/gone.html"]),
+            failed("/down.html", Error::Timeout),
+        ];
+        let broken = broken_links(&results, &Canonicalizer::default());
+        assert_eq!(
+            broken,
+            vec![
+                BrokenLink {
+                    url: url("/gone.html"),
+                    error: Error::HttpStatus(StatusCode::NOT_FOUND),
+                    referrers: vec![
+                        Referrer {
+                            page: url("/"),
+                            text: Some("/gone.html#top".to_owned()),
+                            kind: LinkSource::AnchorHref,
+                        },
+                        Referrer {
+                            page: url("/ok.html"),
+                            text: Some("/gone.html".to_owned()),
+                            kind: LinkSource::AnchorHref,
+                        },
+                    ],
+                },
+                BrokenLink {
+                    url: url("/down.html"),
+                    error: Error::Timeout,
+                    referrers: vec![],
+                },
+            ]
+        );
+    }
+}
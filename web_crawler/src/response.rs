@@ -0,0 +1,163 @@
+//! What was learnt from the response to a page request.
+use crate::{Error, FetchResponse};
+use futures::stream::StreamExt;
+use reqwest::header::{HeaderMap, CONTENT_TYPE, LOCATION};
+use reqwest::{StatusCode, Url, Version};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The response received for a visited URL.
+#[derive(Clone, Debug)]
+pub struct ResponseInfo {
+    /// The HTTP status of the response.
+    pub status: StatusCode,
+    /// The HTTP version the response was received over.
+    pub version: Version,
+    /// The URL the response came from, after following any redirects.
+    pub final_url: Url,
+    /// The redirects followed to reach `final_url`, in order. When a redirect
+    /// is not followed, such as one leaving the crawl's scope, disallowed by
+    /// `robots.txt` or leading to a URL already visited, the last entry leads
+    /// to a URL that was not requested.
+    ///
+    /// Fetchers that follow redirects themselves only report `final_url`.
+    /// Crawlers made by `CrawlerBuilder` follow redirects one at a time, so
+    /// that every hop is recorded.
+    pub redirects: Vec<Redirect>,
+    /// The `Content-Type` header of the response, if any.
+    pub content_type: Option<String>,
+    /// The length of the body in bytes. This is taken from the
+    /// `Content-Length` header, or failing that the body downloaded, if any.
+    pub content_length: Option<u64>,
+    /// Every header of the response.
+    pub headers: HeaderMap,
+    /// The time from sending the request to receiving the whole body, or to
+    /// the body failing. This does not include any time spent waiting on the
+    /// host's rate limits.
+    pub elapsed: Duration,
+}
+
+impl ResponseInfo {
+    /// Records the status and headers of `response`, which has taken
+    /// `elapsed` so far.
+    pub(crate) fn new(response: &FetchResponse, elapsed: Duration) -> Self {
+        Self {
+            status: response.status,
+            version: response.version,
+            final_url: response.url.clone(),
+            redirects: Vec::new(),
+            content_type: response
+                .headers
+                .get(CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned),
+            content_length: response.content_length(),
+            headers: response.headers.clone(),
+            elapsed,
+        }
+    }
+
+    /// Returns `true` if the response is an HTML document, going by its
+    /// `Content-Type`. Responses without one are assumed to be HTML.
+    pub fn is_html(&self) -> bool {
+        is_html(self.content_type.as_deref())
+    }
+
+    /// Returns `true` if the response is a CSS stylesheet, going by its
+    /// `Content-Type`.
+    pub fn is_css(&self) -> bool {
+        is_css(self.content_type.as_deref())
+    }
+}
+
+/// Returns `true` if `content_type` is that of an HTML document, or missing.
+pub(crate) fn is_html(content_type: Option<&str>) -> bool {
+    let content_type = match content_type {
+        Some(content_type) => content_type,
+        None => return true,
+    };
+    let essence = essence(content_type);
+    essence.eq_ignore_ascii_case("text/html")
+        || essence.eq_ignore_ascii_case("application/xhtml+xml")
+}
+
+/// Returns `true` if `content_type` is that of a CSS stylesheet.
+pub(crate) fn is_css(content_type: Option<&str>) -> bool {
+    content_type.is_some_and(|content_type| essence(content_type).eq_ignore_ascii_case("text/css"))
+}
+
+/// Returns the MIME type of `content_type`, without its parameters.
+fn essence(content_type: &str) -> &str {
+    content_type.split(';').next().unwrap_or_default().trim()
+}
+
+/// A single redirect followed during a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redirect {
+    /// The URL that responded with the redirect.
+    pub from: Url,
+    /// The redirect status, such as `301 Moved Permanently`.
+    pub status: StatusCode,
+    /// The URL redirected to.
+    pub to: Url,
+}
+
+/// Returns the URL `response` redirects to, if it is a redirect with a valid
+/// `Location` header. Relative locations are resolved against the URL of the
+/// response.
+pub(crate) fn redirect_location(response: &FetchResponse) -> Option<Url> {
+    if !response.status.is_redirection() {
+        return None;
+    }
+    let location = response.headers.get(LOCATION)?.to_str().ok()?;
+    response.url.join(location).ok()
+}
+
+/// Reads the body of `response`, adding each chunk's length to `downloaded`
+/// as it arrives.
+///
+/// Fails with `Error::TooLarge` as soon as the body is known to be longer than
+/// `max_size` bytes, if given, without reading the rest of it.
+pub(crate) async fn read_body(
+    mut response: FetchResponse,
+    max_size: Option<u64>,
+    downloaded: &AtomicU64,
+) -> Result<Vec<u8>, Error> {
+    let too_large = |len: u64| max_size.filter(|max| len > *max).map(Error::TooLarge);
+    if let Some(e) = response.content_length().and_then(too_large) {
+        return Err(e);
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.body.next().await {
+        let chunk = chunk?;
+        downloaded.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        body.extend_from_slice(&chunk);
+        if let Some(e) = too_large(body.len() as u64) {
+            return Err(e);
+        }
+    }
+    Ok(body)
+}
+
+/// Reads the body of `response` up to its first `max_size` bytes, adding each
+/// chunk's length to `downloaded` as it arrives. The rest of the body is left
+/// unread. Returns the bytes read, and whether they reached `max_size`, in
+/// which case there may be more.
+pub(crate) async fn read_body_prefix(
+    mut response: FetchResponse,
+    max_size: u64,
+    downloaded: &AtomicU64,
+) -> Result<(Vec<u8>, bool), Error> {
+    let mut body = Vec::new();
+    while (body.len() as u64) < max_size {
+        let chunk = match response.body.next().await {
+            None => break,
+            Some(chunk) => chunk?,
+        };
+        downloaded.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        body.extend_from_slice(&chunk);
+    }
+    let cut = body.len() as u64 >= max_size;
+    body.truncate(max_size as usize);
+    Ok((body, cut))
+}
@@ -0,0 +1,300 @@
+//! Parsing and evaluation of `robots.txt` files, as described by RFC 9309.
+use crate::MAX_HOST_INTERVAL;
+use reqwest::Url;
+use std::time::Duration;
+
+/// The product token matched against `User-agent` lines when no other token
+/// is configured.
+pub const DEFAULT_ROBOTS_AGENT: &str = "web_crawler_lib";
+
+/// The most of a `robots.txt` file read, in bytes. RFC 9309 asks crawlers to
+/// parse at least the first 500 KiB, and allows them to ignore the rest.
+pub(crate) const MAX_ROBOTS_SIZE: u64 = 500 * 1024;
+
+/// A single `Allow` or `Disallow` line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Rule {
+    /// The path pattern, which may contain `*` wildcards and a trailing `$`.
+    pattern: String,
+    /// Whether the rule allows (`true`) or disallows (`false`) the pattern.
+    allow: bool,
+}
+
+/// A `User-agent` group as it appears in the file.
+#[derive(Debug, Default)]
+struct Group {
+    /// The lower-cased product tokens the group applies to.
+    agents: Vec<String>,
+    /// The rules listed in the group.
+    rules: Vec<Rule>,
+    /// The `Crawl-delay` listed in the group, if any.
+    crawl_delay: Option<Duration>,
+}
+
+/// The rules of a `robots.txt` file that apply to a single crawler.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Robots {
+    /// The rules from every group that applies to our product token.
+    rules: Vec<Rule>,
+    /// The minimum delay between requests asked for by the site, if any.
+    crawl_delay: Option<Duration>,
+    /// The sitemaps listed by the file, which apply to every crawler.
+    sitemaps: Vec<Url>,
+}
+
+impl Robots {
+    /// Returns rules that allow every URL. This is used when a site has no
+    /// `robots.txt`.
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Returns rules that disallow every URL. This is used when a site's
+    /// `robots.txt` is unreachable.
+    pub fn disallow_all() -> Self {
+        Self {
+            rules: vec![Rule {
+                pattern: "/".to_owned(),
+                allow: false,
+            }],
+            ..Self::default()
+        }
+    }
+
+    /// Parses the `robots.txt` file `body`, keeping the rules that apply to
+    /// the product token `agent`.
+    ///
+    /// Groups naming `agent` (case-insensitively) are used, falling back to
+    /// the `*` groups when there are none. `Sitemap` lines are kept whichever
+    /// group they appear in. Unknown lines are ignored.
+    pub fn parse(body: &str, agent: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut sitemaps = Vec::new();
+        // Whether the last group has seen anything other than `User-agent`
+        // lines. If so, the next `User-agent` line starts a new group.
+        let mut in_rules = true;
+
+        for line in body.lines() {
+            let line = match line.find('#') {
+                Some(i) => &line[..i],
+                None => line,
+            };
+            let (key, value) = match line.find(':') {
+                Some(i) => (line[..i].trim().to_ascii_lowercase(), line[i + 1..].trim()),
+                None => continue,
+            };
+
+            if key == "sitemap" {
+                // `Sitemap` lines belong to no group, and must be absolute.
+                if let Ok(url) = Url::parse(value) {
+                    sitemaps.push(url);
+                }
+                continue;
+            }
+
+            if key == "user-agent" {
+                if in_rules {
+                    groups.push(Group::default());
+                    in_rules = false;
+                }
+                // Checked above: there is always a group to add to.
+                let group = groups.last_mut().unwrap();
+                group.agents.push(value.to_ascii_lowercase());
+                continue;
+            }
+
+            // Lines before the first group apply to nobody.
+            let group = match groups.last_mut() {
+                Some(group) => group,
+                None => continue,
+            };
+            in_rules = true;
+            match key.as_str() {
+                // An empty `Disallow` has no effect.
+                "allow" | "disallow" if !value.is_empty() => group.rules.push(Rule {
+                    pattern: value.to_owned(),
+                    allow: key == "allow",
+                }),
+                "crawl-delay" => {
+                    group.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|d| d.is_finite() && *d >= 0.0)
+                        .map(|d| {
+                            Duration::try_from_secs_f64(d)
+                                .map_or(MAX_HOST_INTERVAL, |d| d.min(MAX_HOST_INTERVAL))
+                        });
+                }
+                _ => {}
+            }
+        }
+
+        let agent = agent.to_ascii_lowercase();
+        let named = |g: &&Group| g.agents.contains(&agent);
+        let any = |g: &&Group| g.agents.iter().any(|a| a == "*");
+        let selected: Vec<&Group> = if groups.iter().any(|g| named(&g)) {
+            groups.iter().filter(named).collect()
+        } else {
+            groups.iter().filter(any).collect()
+        };
+
+        Self {
+            rules: selected
+                .iter()
+                .flat_map(|g| g.rules.iter().cloned())
+                .collect(),
+            crawl_delay: selected.iter().filter_map(|g| g.crawl_delay).max(),
+            sitemaps,
+        }
+    }
+
+    /// Returns `true` if `url` may be crawled.
+    ///
+    /// The longest matching rule decides, with `Allow` winning ties. URLs no
+    /// rule matches are allowed, as is `/robots.txt` itself.
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        };
+        if path == "/robots.txt" {
+            return true;
+        }
+        self.rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, &path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+
+    /// The minimum delay between requests asked for by the site, if any, at
+    /// most `MAX_HOST_INTERVAL`.
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+
+    /// The sitemaps listed by the file.
+    pub fn sitemaps(&self) -> &[Url] {
+        &self.sitemaps
+    }
+}
+
+/// Returns `true` if the robots `pattern` matches the start of `path`.
+///
+/// A `*` in the pattern matches any sequence of characters, and a trailing
+/// `$` requires the match to reach the end of `path`.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    // `split` always yields at least one item.
+    let first = parts.next().unwrap();
+    if !path.starts_with(first) {
+        return false;
+    }
+    let mut pos = first.len();
+    let rest: Vec<&str> = parts.collect();
+    for (i, part) in rest.iter().enumerate() {
+        if anchored && i + 1 == rest.len() {
+            // The final part must sit at the very end of the path.
+            return path.len() >= pos + part.len() && path.ends_with(part);
+        }
+        match path[pos..].find(part) {
+            Some(idx) => pos += idx + part.len(),
+            None => return false,
+        }
+    }
+    !anchored || pos == path.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::url;
+
+    #[test]
+    fn test_pattern_matches() {
+        assert!(pattern_matches("/", "/anything"));
+        assert!(pattern_matches("/fish", "/fish.html"));
+        assert!(!pattern_matches("/fish", "/Fish.html"));
+        assert!(pattern_matches("/*.php", "/folder/filename.php?x=1"));
+        assert!(pattern_matches("/*.php$", "/filename.php"));
+        assert!(!pattern_matches("/*.php$", "/filename.php?x=1"));
+        assert!(pattern_matches("/fish$", "/fish"));
+        assert!(!pattern_matches("/fish$", "/fish/"));
+    }
+
+    #[test]
+    fn test_agent_groups() {
+        let body = "User-agent: *\n\
+                    Disallow: /\n\
+                    \n\
+                    User-agent: Other\n\
+                    User-agent: Web_Crawler_Lib\n\
+                    Disallow: /private # Comment\n\
+                    Crawl-delay: 1.5\n";
+        let robots = Robots::parse(body, DEFAULT_ROBOTS_AGENT);
+        assert!(robots.is_allowed(&url("/public.html")));
+        assert!(!robots.is_allowed(&url("/private/a.html")));
+        assert_eq!(robots.crawl_delay(), Some(Duration::from_millis(1500)));
+
+        let robots = Robots::parse(body, "unknown");
+        assert!(!robots.is_allowed(&url("/public.html")));
+        assert_eq!(robots.crawl_delay(), None);
+    }
+
+    #[test]
+    fn test_huge_crawl_delay() {
+        for delay in ["120", "1e20"] {
+            let body = format!("User-agent: *\nCrawl-delay: {}\n", delay);
+            let robots = Robots::parse(&body, DEFAULT_ROBOTS_AGENT);
+            assert_eq!(robots.crawl_delay(), Some(MAX_HOST_INTERVAL));
+        }
+    }
+
+    #[test]
+    fn test_longest_match() {
+        let body = "User-agent: *\n\
+                    Disallow: /docs/\n\
+                    Allow: /docs/public/\n\
+                    Allow: /page\n\
+                    Disallow: /page\n";
+        let robots = Robots::parse(body, DEFAULT_ROBOTS_AGENT);
+        assert!(!robots.is_allowed(&url("/docs/secret.html")));
+        assert!(robots.is_allowed(&url("/docs/public/a.html")));
+        assert!(robots.is_allowed(&url("/page")));
+    }
+
+    #[test]
+    fn test_sitemaps() {
+        let body = "Sitemap: https://www.enhance.com/sitemap.xml\n\
+                    User-agent: *\n\
+                    Sitemap: https://cdn.enhance.com/sitemap2.xml.gz\n\
+                    Sitemap: /relative.xml\n\
+                    Disallow: /private\n";
+        let robots = Robots::parse(body, DEFAULT_ROBOTS_AGENT);
+        assert_eq!(
+            robots.sitemaps(),
+            &[
+                Url::parse("https://www.enhance.com/sitemap.xml").unwrap(),
+                Url::parse("https://cdn.enhance.com/sitemap2.xml.gz").unwrap(),
+            ]
+        );
+        assert!(!robots.is_allowed(&url("/private")));
+    }
+
+    #[test]
+    fn test_empty_disallow() {
+        let robots = Robots::parse("User-agent: *\nDisallow:\n", DEFAULT_ROBOTS_AGENT);
+        assert!(robots.is_allowed(&url("/anything")));
+    }
+
+    #[test]
+    fn test_disallow_all() {
+        let robots = Robots::disallow_all();
+        assert!(!robots.is_allowed(&url("/anything")));
+        assert!(robots.is_allowed(&url("/robots.txt")));
+    }
+}
@@ -0,0 +1,210 @@
+//! Parsing of XML sitemaps and sitemap indexes, as described at
+//! <https://www.sitemaps.org/protocol.html>.
+use flate2::read::GzDecoder;
+use quick_xml::events::Event;
+use quick_xml::name::{Namespace, ResolveResult};
+use quick_xml::NsReader;
+use reqwest::Url;
+use std::borrow::Cow;
+use std::io::Read;
+
+/// The largest uncompressed sitemap the protocol allows, in bytes. Larger
+/// gzip'd sitemaps are truncated to this size, and larger bodies are not
+/// read at all.
+pub(crate) const MAX_SITEMAP_SIZE: u64 = 50 * 1024 * 1024;
+
+/// The most sitemaps requested by a crawl, counting those listed by
+/// `robots.txt` and sitemap indexes along with each origin's `/sitemap.xml`.
+pub(crate) const MAX_SITEMAPS: usize = 1_000;
+
+/// The namespace of the elements of sitemaps and sitemap indexes.
+const SITEMAP_NAMESPACE: Namespace<'static> =
+    Namespace(b"http://www.sitemaps.org/schemas/sitemap/0.9");
+
+/// The URLs listed by a sitemap file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Sitemap {
+    /// A `<urlset>`, listing pages.
+    UrlSet(Vec<Url>),
+    /// A `<sitemapindex>`, listing further sitemaps.
+    Index(Vec<Url>),
+}
+
+impl Sitemap {
+    /// Parses the sitemap file `body`, which may be gzip'd.
+    ///
+    /// Returns `None` if `body` is neither a `<urlset>` nor a
+    /// `<sitemapindex>` in the sitemap namespace. Only the `<loc>` of each
+    /// `<url>` or `<sitemap>` entry is read, so the `<loc>` elements of
+    /// extensions, such as `<image:loc>`, are not taken for pages. Malformed
+    /// XML ends the parse early, keeping the entries read up to that point.
+    /// Entries that are not absolute URLs are ignored.
+    pub fn parse(body: &[u8]) -> Option<Self> {
+        let xml = decompress(body)?;
+        let mut reader = NsReader::from_reader(xml.as_ref());
+        reader.trim_text(true);
+
+        let mut buf = Vec::new();
+        // Whether the root element is a `<sitemapindex>`, once seen.
+        let mut is_index = None;
+        // The local name of each open element, empty for those outside the
+        // sitemap namespace.
+        let mut open: Vec<Vec<u8>> = Vec::new();
+        let mut in_loc = false;
+        let mut loc = String::new();
+        let mut locs = Vec::new();
+        loop {
+            match reader.read_resolved_event_into(&mut buf) {
+                Ok((namespace, Event::Start(e))) => {
+                    let name = match namespace {
+                        ResolveResult::Bound(SITEMAP_NAMESPACE) => e.local_name().as_ref().to_vec(),
+                        _ => Vec::new(),
+                    };
+                    match (open.len(), name.as_slice()) {
+                        (0, b"urlset") => is_index = Some(false),
+                        (0, b"sitemapindex") => is_index = Some(true),
+                        (2, b"loc") => {
+                            let entry: &[u8] = match is_index {
+                                Some(true) => b"sitemap",
+                                _ => b"url",
+                            };
+                            in_loc = is_index.is_some() && open[1] == entry;
+                            loc.clear();
+                        }
+                        _ => {}
+                    }
+                    open.push(name);
+                }
+                Ok((_, Event::Text(t))) if in_loc => match t.unescape() {
+                    Ok(text) => loc.push_str(&text),
+                    Err(_) => break,
+                },
+                Ok((_, Event::CData(c))) if in_loc => loc.push_str(&String::from_utf8_lossy(&c)),
+                Ok((_, Event::End(_))) => {
+                    open.pop();
+                    if in_loc {
+                        in_loc = false;
+                        if let Ok(url) = Url::parse(loc.trim()) {
+                            locs.push(url);
+                        }
+                    }
+                }
+                Ok((_, Event::Eof)) | Err(_) => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        match is_index? {
+            true => Some(Sitemap::Index(locs)),
+            false => Some(Sitemap::UrlSet(locs)),
+        }
+    }
+}
+
+/// Returns `body` with any gzip compression removed.
+fn decompress(body: &[u8]) -> Option<Cow<'_, [u8]>> {
+    if !body.starts_with(&[0x1f, 0x8b]) {
+        return Some(Cow::Borrowed(body));
+    }
+    let mut xml = Vec::new();
+    GzDecoder::new(body)
+        .take(MAX_SITEMAP_SIZE)
+        .read_to_end(&mut xml)
+        .ok()?;
+    Some(Cow::Owned(xml))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const URLSET: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+          <url>
+            <loc>https://www.enhance.com/</loc>
+            <lastmod>2005-01-01</lastmod>
+          </url>
+          <url>
+            <loc>https://www.enhance.com/catalog?item=12&amp;desc=vacation</loc>
+          </url>
+          <url><loc><![CDATA[https://www.enhance.com/cdata.html]]></loc></url>
+          <url><loc>not a url</loc></url>
+        </urlset>"#;
+
+    #[test]
+    fn test_urlset() {
+        let sitemap = Sitemap::parse(URLSET.as_bytes()).unwrap();
+        assert_eq!(
+            sitemap,
+            Sitemap::UrlSet(vec![
+                Url::parse("https://www.enhance.com/").unwrap(),
+                Url::parse("https://www.enhance.com/catalog?item=12&desc=vacation").unwrap(),
+                Url::parse("https://www.enhance.com/cdata.html").unwrap(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_index() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <sitemap><loc>https://www.enhance.com/sitemap1.xml.gz</loc></sitemap>
+            </sitemapindex>"#;
+        let sitemap = Sitemap::parse(body.as_bytes()).unwrap();
+        assert_eq!(
+            sitemap,
+            Sitemap::Index(vec![
+                Url::parse("https://www.enhance.com/sitemap1.xml.gz").unwrap()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(URLSET.as_bytes()).unwrap();
+        let body = encoder.finish().unwrap();
+        assert_eq!(Sitemap::parse(&body), Sitemap::parse(URLSET.as_bytes()));
+    }
+
+    #[test]
+    fn test_not_a_sitemap() {
+        assert_eq!(Sitemap::parse(b"<html><body></body></html>"), None);
+        // Without the sitemap namespace, the elements are not a sitemap's.
+        assert_eq!(
+            Sitemap::parse(b"<urlset><url><loc>https://a.com/</loc></url></urlset>"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extension_locs() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"
+                    xmlns:image="http://www.google.com/schemas/sitemap-image/1.1"
+                    xmlns:video="http://www.google.com/schemas/sitemap-video/1.1">
+              <url>
+                <loc>https://www.enhance.com/gallery.html</loc>
+                <image:image>
+                  <image:loc>https://www.enhance.com/photo.jpg</image:loc>
+                </image:image>
+                <video:video>
+                  <video:content_loc>https://www.enhance.com/video.mp4</video:content_loc>
+                  <loc>https://www.enhance.com/nested.html</loc>
+                </video:video>
+              </url>
+              <loc>https://www.enhance.com/stray.html</loc>
+            </urlset>"#;
+        assert_eq!(
+            Sitemap::parse(body.as_bytes()),
+            Some(Sitemap::UrlSet(vec![Url::parse(
+                "https://www.enhance.com/gallery.html"
+            )
+            .unwrap()]))
+        );
+    }
+}
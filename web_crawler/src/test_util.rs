@@ -0,0 +1,45 @@
+//! Fixtures shared by the unit tests of each module.
+#![cfg(test)]
+
+use crate::{CrawlOutcome, CrawlResult, CrawlerBuilder, Link, LinkSource};
+use futures::stream::StreamExt;
+use reqwest::Url;
+
+/// Returns `path` resolved against `https://www.enhance.com/`.
+pub(crate) fn url(path: &str) -> Url {
+    Url::parse("https://www.enhance.com/")
+        .unwrap()
+        .join(path)
+        .unwrap()
+}
+
+/// Returns the result of visiting `path`, with an `<a>` link to each of
+/// `links`, whose text is the link as written.
+pub(crate) fn result(path: &str, links: &[&str]) -> CrawlResult {
+    CrawlResult {
+        url: url(path),
+        original_url: url(path),
+        depth: 0,
+        referrer: None,
+        outcome: CrawlOutcome::Visited,
+        response: None,
+        links: links
+            .iter()
+            .map(|link| Link {
+                url: url(link),
+                source: LinkSource::AnchorHref,
+                nofollow: false,
+                text: Some(link.to_string()),
+            })
+            .collect(),
+        noindex: false,
+        canonical: None,
+        data: None,
+    }
+}
+
+/// Crawls from `start` with the crawler `builder` produces, returning every
+/// result in the order the stream yields them.
+pub(crate) async fn crawl(builder: CrawlerBuilder, start: Url) -> Vec<CrawlResult> {
+    builder.build().unwrap().crawl(start).collect().await
+}
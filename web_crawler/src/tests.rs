@@ -2,21 +2,37 @@
 #![cfg(test)]
 
 use super::*;
+use crate::test_util::{crawl, url};
 use mockito::{mock, Mock};
+use reqwest::redirect::Policy;
+use reqwest::{Client, StatusCode};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 /// Helper method to generate a simple `CrawlStreamState`.
-fn default_state() -> CrawlStreamState {
+fn default_state() -> CrawlStreamState<()> {
     let client = Client::new();
     let url = Url::parse("https://www.enhance.com/").unwrap();
-    CrawlStreamState::create(client, url)
+    CrawlStreamState::create(Arc::new(client), Default::default(), Arc::new(()), url)
+}
+
+/// Helper method to queue `url` as the starting URL of a crawl.
+fn queued(url: Url) -> QueuedUrl {
+    QueuedUrl {
+        url: url.clone(),
+        original: url,
+        depth: 0,
+        referrer: None,
+        redirected: None,
+    }
 }
 
 /// Tests the default construction of State fro a URL.
 #[test]
 fn test_create_state() {
     let crawl_state = default_state();
-    assert_eq!(crawl_state.visited.iter().count(), 0);
-    assert_eq!(crawl_state.to_visit.iter().count(), 1);
+    assert_eq!(crawl_state.visited.len(), 0);
+    assert_eq!(crawl_state.to_visit.len(), 1);
 }
 
 /// Tests adding an unvisited URL to the to-visit list.
@@ -24,10 +40,10 @@ fn test_create_state() {
 fn test_add_unvisited() {
     let mut crawl_state = default_state();
     let url = Url::from_str("https://www.enhance.com/").unwrap();
-    let res = crawl_state.add_url_to_queue(url);
+    let res = crawl_state.add_url_to_queue(url, 1, None);
     assert_eq!(res, Some(()));
-    assert_eq!(crawl_state.visited.iter().count(), 0);
-    assert_eq!(crawl_state.to_visit.iter().count(), 2);
+    assert_eq!(crawl_state.visited.len(), 0);
+    assert_eq!(crawl_state.to_visit.len(), 2);
 }
 
 /// Tests adding a visited URL to the to-visit list.
@@ -36,19 +52,27 @@ fn test_add_visited() {
     let mut crawl_state = default_state();
     let url = Url::from_str("https://www.google.com/").unwrap();
     crawl_state.visited.insert(url.clone());
-    let res = crawl_state.add_url_to_queue(url);
+    let res = crawl_state.add_url_to_queue(url, 1, None);
     assert_eq!(res, None);
-    assert_eq!(crawl_state.visited.iter().count(), 1);
-    assert_eq!(crawl_state.to_visit.iter().count(), 1);
+    assert_eq!(crawl_state.visited.len(), 1);
+    assert_eq!(crawl_state.to_visit.len(), 1);
 }
 
 /// Tests accessing a nonexistant document.
 #[tokio::test]
 async fn test_document_for_url_failure() {
-    let crawl_state = default_state();
     let url = Url::parse("https://foo.invalid/failure.html").unwrap();
-    let result = crawl_state.document_for_url(&url).await;
-    assert!(result.is_err())
+    let error = Error::Dns("failed to lookup address information".to_owned());
+    let fetcher = MemoryFetcher::new().with_error(url.clone(), error.clone());
+    let crawl_state = CrawlStreamState::create(
+        Arc::new(fetcher),
+        Default::default(),
+        Arc::new(()),
+        url.clone(),
+    );
+    let (_, response, result) = crawl_state.document_for_url(&queued(url)).await;
+    assert!(response.is_none());
+    assert_eq!(result.unwrap_err(), error);
 }
 
 /// Attempts to create a valid URL to the `res` folder which contains
@@ -56,12 +80,17 @@ async fn test_document_for_url_failure() {
 fn simple_html() -> Mock {
     mock("GET", "/simple.html")
         .with_status(201)
-        .with_header("content-type", "text/plain")
+        .with_header("content-type", "text/html")
         .with_header("x-api-key", "1234")
         .with_body(include_str!("../res/simple.html"))
         .create()
 }
 
+/// Mocks a missing `robots.txt`, which allows crawling everything.
+fn robots_txt() -> Mock {
+    mock("GET", "/robots.txt").with_status(404).create()
+}
+
 /// Create a URL to the resource `filename` in the mockio mock server.
 fn mock_url(filename: &str) -> Url {
     let url = Url::parse(&mockito::server_url()).unwrap();
@@ -73,32 +102,628 @@ fn mock_url(filename: &str) -> Url {
 async fn test_document_for_url_success() {
     let _m = simple_html();
     let crawl_state = default_state();
-    let result = crawl_state.document_for_url(&mock_url("simple.html")).await;
+    let (_, _, result) = crawl_state
+        .document_for_url(&queued(mock_url("simple.html")))
+        .await;
+    let body = match result.unwrap() {
+        Body::Html(body) => body,
+        body => panic!("Expected an HTML body, got {:?}", body),
+    };
     assert_eq!(
-        result.unwrap(),
+        Html::parse_document(&body),
         Html::parse_document(include_str!("../res/simple.html"))
     )
 }
 
+/// Tests a document is decoded with the charset of its `Content-Type`.
+#[tokio::test]
+async fn test_document_for_url_charset() {
+    let _m = mock("GET", "/latin1.html")
+        .with_status(200)
+        .with_header("content-type", "text/html; charset=windows-1252")
+        .with_body(&b"<p>caf\xe9</p>"[..])
+        .create();
+    let crawl_state = default_state();
+    let (_, _, result) = crawl_state
+        .document_for_url(&queued(mock_url("latin1.html")))
+        .await;
+    match result.unwrap() {
+        Body::Html(body) => assert_eq!(body, "<p>caf\u{e9}</p>"),
+        body => panic!("Expected an HTML body, got {:?}", body),
+    }
+}
+
+/// Tests a page declaring no charset, but valid as UTF-8, is decoded as
+/// UTF-8, so its non-ASCII links are extracted as written.
+#[tokio::test]
+async fn test_crawl_undeclared_utf8() {
+    let fetcher = MemoryFetcher::new().with_page(
+        url("/"),
+        "text/html",
+        "<a href=\"/caf\u{e9}.html\">Caf\u{e9}</a>",
+    );
+    let results = crawl(CrawlerBuilder::new().fetcher(fetcher), url("/")).await;
+    let link = &results[0].links[0];
+    assert_eq!(link.url, url("/caf%C3%A9.html"));
+    assert_eq!(link.text.as_deref(), Some("Caf\u{e9}"));
+}
+
+/// Mocks a page at `path` that links to a stylesheet, `/style.css`.
+fn stylesheet_html(path: &str) -> Mock {
+    mock("GET", path)
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<link rel="stylesheet" href="/style.css">"#)
+        .create()
+}
+
+/// Tests a resource that is not HTML is visited, but its body is not read.
+#[tokio::test]
+async fn test_crawl_not_html() {
+    let _m1 = stylesheet_html("/styled.html");
+    let _m2 = mock("GET", "/style.css")
+        .with_status(200)
+        .with_header("content-type", "text/css")
+        .with_body("a { background: url(/never.png) }")
+        .create();
+    let _m3 = robots_txt();
+
+    let crawler = Crawler::new(Client::new(), CrawlConfig::default());
+    let mut stream = crawler.crawl(mock_url("styled.html"));
+    let mut results = Vec::new();
+    while let Some(result) = stream.next().await {
+        results.push(result);
+    }
+    assert_eq!(results.len(), 2);
+    let css = results[1].response.as_ref().unwrap();
+    assert_eq!(results[1].outcome, CrawlOutcome::Visited);
+    assert_eq!(css.content_type.as_deref(), Some("text/css"));
+    assert!(!css.is_html());
+}
+
+/// Tests the links of stylesheets, and of `<style>` elements, are followed
+/// when a CSS source is configured.
+#[tokio::test]
+async fn test_crawl_css_links() {
+    let _m1 = mock("GET", "/styled.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(
+            r#"<link rel="stylesheet" href="/css/style.css">
+            <style>@import "/css/print.css";</style>"#,
+        )
+        .create();
+    let _m2 = mock("GET", "/css/style.css")
+        .with_status(200)
+        .with_header("content-type", "text/css")
+        .with_body("@import url(theme.css); a { background: url('/bg.png') }")
+        .create();
+    let _m3 = mock("GET", "/css/print.css")
+        .with_status(200)
+        .with_header("content-type", "text/css")
+        .with_body("body { color: black }")
+        .create();
+    let _m4 = mock("GET", "/css/theme.css")
+        .with_status(200)
+        .with_header("content-type", "text/css")
+        .with_body("body { font-family: serif }")
+        .create();
+    let _m5 = robots_txt();
+
+    let mut sources = LinkSource::DEFAULT.to_vec();
+    sources.push(LinkSource::CssImport);
+    let builder = CrawlerBuilder::new().link_sources(&sources);
+    let results = crawl(builder, mock_url("styled.html")).await;
+    let mut urls: Vec<Url> = results.iter().map(|r| r.url.clone()).collect();
+    urls.sort();
+    assert_eq!(
+        urls,
+        vec![
+            mock_url("css/print.css"),
+            mock_url("css/style.css"),
+            mock_url("css/theme.css"),
+            mock_url("styled.html"),
+        ]
+    );
+    // Only the configured CSS source is followed.
+    let style = results
+        .iter()
+        .find(|r| r.url == mock_url("css/style.css"))
+        .unwrap();
+    assert_eq!(style.links.len(), 1);
+    assert_eq!(style.links[0].source, LinkSource::CssImport);
+}
+
+/// Tests links are followed from every configured source.
+#[tokio::test]
+async fn test_crawl_link_sources() {
+    let _m1 = mock("GET", "/gallery.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<img src="/photo.png"><script src="/app.js"></script>"#)
+        .create();
+    let _m2 = mock("GET", "/photo.png")
+        .with_status(200)
+        .with_header("content-type", "image/png")
+        .create();
+    let _m3 = robots_txt();
+
+    let builder = CrawlerBuilder::new().link_sources(&[LinkSource::ImgSrc]);
+    let urls: Vec<Url> = crawl(builder, mock_url("gallery.html"))
+        .await
+        .into_iter()
+        .map(|r| r.url)
+        .collect();
+    assert_eq!(urls, vec![mock_url("gallery.html"), mock_url("photo.png")]);
+}
+
+/// Tests `nofollow` links and pages are not followed when configured, and
+/// `noindex` is reported.
+#[tokio::test]
+async fn test_crawl_nofollow() {
+    let _m1 = mock("GET", "/links.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(
+            r#"<meta name="robots" content="noindex">
+            <a href="/private.html" rel="nofollow">Private</a>
+            <a href="/header.html">Header</a>"#,
+        )
+        .create();
+    let _m2 = mock("GET", "/header.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_header("x-robots-tag", "nofollow")
+        .with_body(r#"<a href="/hidden.html">Hidden</a>"#)
+        .create();
+    let _m3 = robots_txt();
+
+    let builder = CrawlerBuilder::new().respect_nofollow(true);
+    let results: Vec<(Url, bool)> = crawl(builder, mock_url("links.html"))
+        .await
+        .into_iter()
+        .map(|r| (r.url, r.noindex))
+        .collect();
+    assert_eq!(
+        results,
+        vec![
+            (mock_url("links.html"), true),
+            (mock_url("header.html"), false)
+        ]
+    );
+}
+
+/// Tests pages declaring a canonical URL are clustered under it, and the
+/// declaration is not followed as a link.
+#[tokio::test]
+async fn test_crawl_canonical() {
+    let _m1 = mock("GET", "/shop.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(
+            r#"<link rel="canonical" href="/shop.html">
+            <a href="/shop.html?ref=home">Shop</a>"#,
+        )
+        .create();
+    let _m2 = mock("GET", "/shop.html?ref=home")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<link rel="canonical" href="/shop.html#top">"#)
+        .create();
+    let _m3 = mock("GET", "/canonical.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<link rel="canonical" href="/shop.html">"#)
+        .expect(0)
+        .create();
+    let _m4 = robots_txt();
+
+    let results = crawl(CrawlerBuilder::new(), mock_url("shop.html")).await;
+    assert_eq!(results.len(), 2);
+    assert!(results
+        .iter()
+        .all(|r| r.canonical == Some(mock_url("shop.html"))));
+
+    let clusters = canonical_clusters(&results);
+    assert_eq!(clusters.len(), 1);
+    let mut cluster = clusters[&mock_url("shop.html")].clone();
+    cluster.sort();
+    assert_eq!(
+        cluster,
+        vec![mock_url("shop.html"), mock_url("shop.html?ref=home")]
+    );
+    _m3.assert();
+}
+
+/// Tests the link graph of a crawl has an edge for every link found, with its
+/// text and kind.
+#[tokio::test]
+async fn test_link_graph() {
+    let _m1 = mock("GET", "/home.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(
+            r#"<a href="/about.html#team">About <b>us</b></a>
+            <iframe src="https://www.google.com/embed"></iframe>"#,
+        )
+        .create();
+    let _m2 = mock("GET", "/about.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<a href="/home.html">Home</a>"#)
+        .create();
+    let _m3 = robots_txt();
+
+    let crawler = CrawlerBuilder::new()
+        .link_sources(LinkSource::PAGES)
+        .build()
+        .unwrap();
+    let graph = crawler.link_graph(mock_url("home.html")).await;
+    let edges: Vec<(Url, Url, Option<&str>, LinkSource)> = graph
+        .edges()
+        .iter()
+        .map(|e| {
+            (
+                e.source.clone(),
+                e.target.clone(),
+                e.text.as_deref(),
+                e.kind,
+            )
+        })
+        .collect();
+    let google = Url::parse("https://www.google.com/embed").unwrap();
+    assert_eq!(
+        edges,
+        vec![
+            (
+                mock_url("home.html"),
+                mock_url("about.html"),
+                Some("About us"),
+                LinkSource::AnchorHref
+            ),
+            (
+                mock_url("home.html"),
+                google.clone(),
+                None,
+                LinkSource::IframeSrc
+            ),
+            (
+                mock_url("about.html"),
+                mock_url("home.html"),
+                Some("Home"),
+                LinkSource::AnchorHref
+            ),
+        ]
+    );
+    assert!(!graph.get(&google).unwrap().visited);
+    assert!(graph.orphans().is_empty());
+}
+
+/// Tests the data extracted from each HTML page is attached to its result.
+#[tokio::test]
+async fn test_crawl_with_extractor() {
+    let _m1 = mock("GET", "/article.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(
+            r#"<title>Article</title>
+            <meta name="description" content="An article.">
+            <h1>Heading</h1><a href="/style.css">Style</a>"#,
+        )
+        .create();
+    let _m2 = mock("GET", "/style.css")
+        .with_status(200)
+        .with_header("content-type", "text/css")
+        .create();
+    let _m3 = robots_txt();
+
+    let crawler = CrawlerBuilder::new().build().unwrap();
+    let results: Vec<_> = crawler
+        .crawl_with(mock_url("article.html"), (Title, MetaDescription, Headings))
+        .collect()
+        .await;
+    assert_eq!(results.len(), 2);
+    let (title, description, headings) = results[0].data.clone().unwrap();
+    assert_eq!(title.as_deref(), Some("Article"));
+    assert_eq!(description.as_deref(), Some("An article."));
+    assert_eq!(
+        headings,
+        vec![Heading {
+            level: 1,
+            text: "Heading".to_owned()
+        }]
+    );
+    // Resources that are not HTML have no data.
+    assert!(results[1].data.is_none());
+}
+
+/// Tests a crawl through a `MemoryFetcher`, which needs no server.
+#[tokio::test]
+async fn test_crawl_memory_fetcher() {
+    let fetcher = MemoryFetcher::new()
+        .with_page(
+            url("/"),
+            "text/html",
+            r#"<a href="/old.html">Old</a><a href="/missing.html">Missing</a>"#,
+        )
+        .with_redirect(url("/old.html"), &url("/new.html"))
+        .with_page(url("/new.html"), "text/html", "<p>New</p>");
+    let mut results = crawl(CrawlerBuilder::new().fetcher(fetcher), url("/")).await;
+    results.sort_by(|a, b| a.url.cmp(&b.url));
+    let outcomes: Vec<(Url, CrawlOutcome)> = results
+        .iter()
+        .map(|r| (r.url.clone(), r.outcome.clone()))
+        .collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            (url("/"), CrawlOutcome::Visited),
+            (
+                url("/missing.html"),
+                CrawlOutcome::Failed(Error::HttpStatus(StatusCode::NOT_FOUND))
+            ),
+            (url("/old.html"), CrawlOutcome::Visited),
+        ]
+    );
+    let response = results[2].response.as_ref().unwrap();
+    assert_eq!(response.final_url, url("/new.html"));
+    assert_eq!(response.redirects.len(), 1);
+}
+
+/// Tests in-scope URLs outside the include patterns, or matching an exclude
+/// pattern, are left out of a crawl.
+#[tokio::test]
+async fn test_crawl_include_exclude() {
+    let fetcher = MemoryFetcher::new()
+        .with_page(
+            url("/docs/"),
+            "text/html",
+            r#"<a href="/docs/a.html">A</a><a href="/docs/?sort=date">Sorted</a>
+            <a href="/blog/">Blog</a>"#,
+        )
+        .with_page(url("/docs/a.html"), "text/html", "<p>A</p>")
+        .with_page(url("/docs/?sort=date"), "text/html", "<p>Sorted</p>")
+        .with_page(url("/blog/"), "text/html", "<p>Blog</p>");
+    let builder = CrawlerBuilder::new()
+        .fetcher(fetcher)
+        .include(UrlPattern::glob("/docs/**"))
+        .exclude(UrlPattern::glob("**?sort=*"));
+    let mut urls: Vec<Url> = crawl(builder, url("/docs/"))
+        .await
+        .into_iter()
+        .map(|r| r.url)
+        .collect();
+    urls.sort();
+    assert_eq!(urls, vec![url("/docs/"), url("/docs/a.html")]);
+}
+
+/// Tests a crawl follows links to hosts within its scope, and leaves out
+/// those on other hosts.
+#[tokio::test]
+async fn test_crawl_scope() {
+    let docs = Url::parse("https://docs.enhance.com/").unwrap();
+    let google = Url::parse("https://www.google.com/").unwrap();
+    let fetcher = MemoryFetcher::new()
+        .with_page(
+            url("/"),
+            "text/html",
+            r#"<a href="https://docs.enhance.com/">Docs</a>
+            <a href="https://www.google.com/">Google</a>"#,
+        )
+        .with_page(docs.clone(), "text/html", "<p>Docs</p>")
+        .with_page(google, "text/html", "<p>Google</p>");
+    let builder = CrawlerBuilder::new()
+        .fetcher(fetcher.clone())
+        .scope(Scope::RegistrableDomain);
+    let mut urls: Vec<Url> = crawl(builder, url("/"))
+        .await
+        .into_iter()
+        .map(|r| r.url)
+        .collect();
+    urls.sort();
+    assert_eq!(urls, vec![docs, url("/")]);
+
+    // `docs.enhance.com` is not a subdomain of `www.enhance.com`.
+    let builder = CrawlerBuilder::new()
+        .fetcher(fetcher)
+        .scope(Scope::HostAndSubdomains);
+    let urls: Vec<Url> = crawl(builder, url("/"))
+        .await
+        .into_iter()
+        .map(|r| r.url)
+        .collect();
+    assert_eq!(urls, vec![url("/")]);
+}
+
+/// Tests every exchange of a crawl is archived, with the bodies that were
+/// read, including those for its `robots.txt` file and sitemaps.
+#[tokio::test]
+async fn test_crawl_warc() {
+    let sitemap = r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+        <url><loc>https://www.enhance.com/</loc></url>
+        </urlset>"#;
+    let fetcher = MemoryFetcher::new()
+        .with_page(
+            url("/"),
+            "text/html",
+            r#"<a href="/old.html">Old</a><a href="/report.pdf">Report</a>"#,
+        )
+        .with_redirect(url("/old.html"), &url("/new.html"))
+        .with_page(url("/new.html"), "text/html", "<p>New</p>")
+        .with_page(url("/report.pdf"), "application/pdf", "%PDF-1.4")
+        .with_page(
+            url("/robots.txt"),
+            "text/plain",
+            "Sitemap: https://www.enhance.com/sitemap.xml\n",
+        )
+        .with_page(url("/sitemap.xml"), "application/xml", sitemap);
+    let dir = std::env::temp_dir().join(format!("warc-crawl-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let options = WarcOptions {
+        gzip: false,
+        ..WarcOptions::default()
+    };
+    let crawler = CrawlerBuilder::new()
+        .fetcher(fetcher)
+        .sitemaps(true)
+        .warc(WarcWriter::new(&dir, options))
+        .build()
+        .unwrap();
+    assert_eq!(crawler.unique_url_count(url("/")).await, 3);
+
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(files.len(), 1);
+    let warc = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+    assert_eq!(warc.matches("WARC-Type: request\r\n").count(), 6);
+    assert_eq!(warc.matches("WARC-Type: response\r\n").count(), 6);
+    assert!(warc.contains("GET /old.html HTTP/1.1\r\n"));
+    assert!(warc.contains("HTTP/1.1 301 Moved Permanently\r\n"));
+    assert!(warc.contains("\r\n\r\n<p>New</p>\r\n\r\n"));
+    assert!(warc.contains("\r\n\r\nSitemap: https://www.enhance.com/sitemap.xml\n\r\n\r\n"));
+    assert!(warc.contains("GET /sitemap.xml HTTP/1.1\r\n"));
+    assert!(warc.contains("<loc>https://www.enhance.com/</loc>"));
+    // The PDF is not read, so its record says so.
+    assert_eq!(warc.matches("WARC-Truncated: unspecified\r\n").count(), 1);
+    assert!(!warc.contains("%PDF"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Tests a `robots.txt` file that cannot be archived still applies, and the
+/// archive error is reported on a result of its own.
+#[tokio::test]
+async fn test_crawl_robots_archive_error() {
+    let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), "text/html", "<p>Home</p>")
+        .with_page(
+            url("/robots.txt"),
+            "text/plain",
+            "User-agent: *\nAllow: /\n",
+        );
+    // A file where the directory should be makes every write fail.
+    let dir = std::env::temp_dir().join(format!("warc-robots-{}", std::process::id()));
+    std::fs::write(&dir, "").unwrap();
+    let builder = CrawlerBuilder::new()
+        .fetcher(fetcher)
+        .warc(WarcWriter::new(&dir, WarcOptions::default()));
+    let results = crawl(builder, url("/")).await;
+    std::fs::remove_file(&dir).unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].url, url("/robots.txt"));
+    assert!(matches!(
+        results[0].outcome,
+        CrawlOutcome::Failed(Error::Archive(_))
+    ));
+    // The page is requested, rather than disallowed, but cannot be archived
+    // either.
+    assert_eq!(results[1].url, url("/"));
+    assert!(matches!(
+        results[1].outcome,
+        CrawlOutcome::Failed(Error::Archive(_))
+    ));
+}
+
+/// Tests a probe of a URL is archived like a crawl's requests.
+#[tokio::test]
+async fn test_probe_warc() {
+    let fetcher = MemoryFetcher::new().with_page(url("/"), "text/html", "<p>Home</p>");
+    let dir = std::env::temp_dir().join(format!("warc-probe-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let options = WarcOptions {
+        gzip: false,
+        ..WarcOptions::default()
+    };
+    let crawler = CrawlerBuilder::new()
+        .fetcher(fetcher)
+        .warc(WarcWriter::new(&dir, options))
+        .build()
+        .unwrap();
+    let response = crawler.probe(url("/")).await.unwrap();
+    assert_eq!(response.status, StatusCode::OK);
+
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    let warc = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+    assert!(warc.contains("HEAD / HTTP/1.1\r\n"));
+    assert!(!warc.contains("<p>Home</p>"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Tests a HEAD request that finds a resource that is not HTML saves the GET.
+#[tokio::test]
+async fn test_crawl_head_first() {
+    let _m1 = mock("HEAD", "/styled.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .create();
+    let _m2 = stylesheet_html("/styled.html");
+    let _m3 = mock("HEAD", "/style.css")
+        .with_status(200)
+        .with_header("content-type", "text/css")
+        .create();
+    let get_css = mock("GET", "/style.css").expect(0).create();
+    let _m4 = robots_txt();
+
+    let builder = CrawlerBuilder::new().head_first(true);
+    let results = crawl(builder, mock_url("styled.html")).await;
+    assert_eq!(results.len(), 2);
+    get_css.assert();
+}
+
 /// Tests processing a document in the `to_visit` list, which triggers the
 /// crawl state to change.
 #[tokio::test]
 async fn test_pop() {
     let _m = simple_html();
+    let _r = robots_txt();
 
     let client = Client::new();
     let url = mock_url("simple.html");
-    let crawl_state = CrawlStreamState::create(client, url.clone());
+    let crawl_state = CrawlStreamState::create(
+        Arc::new(client),
+        Default::default(),
+        Arc::new(()),
+        url.clone(),
+    );
 
     if let Some((result, new_state)) = crawl_state.pop_document().await {
-        assert_eq!(new_state.visited.iter().count(), 1);
-        assert_eq!(new_state.to_visit.iter().count(), 1);
+        assert_eq!(new_state.visited.len(), 1);
+        assert_eq!(new_state.to_visit.len(), 1);
 
         assert_eq!(result.url, url);
+        assert_eq!(result.depth, 0);
+        assert_eq!(result.referrer, None);
         assert!(new_state.visited.contains(&url));
+
+        let response = result.response.unwrap();
+        assert_eq!(response.status, 201);
+        assert_eq!(response.final_url, url);
+        assert_eq!(response.content_type.as_deref(), Some("text/html"));
+        assert_eq!(
+            response.content_length,
+            Some(include_str!("../res/simple.html").len() as u64)
+        );
+        assert_eq!(response.headers["x-api-key"], "1234");
+
+        let links: Vec<(&str, LinkSource)> = result
+            .links
+            .iter()
+            .map(|link| (link.url.as_str(), link.source))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                ("https://www.enhance.com/", LinkSource::AnchorHref),
+                (mock_url("link_node.html").as_str(), LinkSource::AnchorHref),
+            ]
+        );
         assert_eq!(
             new_state.to_visit.peek().unwrap(),
-            &mock_url("link_node.html")
+            &QueuedUrl {
+                url: mock_url("link_node.html"),
+                original: mock_url("link_node.html"),
+                depth: 1,
+                referrer: Some(url.clone()),
+                redirected: None,
+            }
         );
     } else {
         panic!("Expected valid document pop.")
@@ -110,7 +735,7 @@ async fn test_pop() {
 fn self_ref_html() -> Mock {
     mock("GET", "/self_ref.html")
         .with_status(201)
-        .with_header("content-type", "text/plain")
+        .with_header("content-type", "text/html")
         .with_header("x-api-key", "1234")
         .with_body(include_str!("../res/self_ref.html"))
         .create()
@@ -121,7 +746,7 @@ fn self_ref_html() -> Mock {
 fn link_node_html() -> Mock {
     mock("GET", "/link_node.html")
         .with_status(201)
-        .with_header("content-type", "text/plain")
+        .with_header("content-type", "text/html")
         .with_header("x-api-key", "1234")
         .with_body(include_str!("../res/link_node.html"))
         .create()
@@ -133,14 +758,773 @@ async fn test_crawl() {
     let _m1 = simple_html();
     let _m2 = self_ref_html();
     let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let client = Client::new();
+    let url = mock_url("simple.html");
+
+    let results: Vec<CrawlResult> = crawl_domain_with_client(client, url).collect().await;
+    assert_eq!(results.len(), 3);
+}
+
+/// Tests a crawl that only ever has a single request in flight visits the same
+/// URLs as a concurrent crawl.
+#[tokio::test]
+async fn test_crawl_sequential() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let client = Client::new();
+    let url = mock_url("simple.html");
+
+    let results: Vec<CrawlResult> = crawl_domain_concurrent_with_client(client, url, 1)
+        .collect()
+        .await;
+    assert_eq!(results.len(), 3);
+}
+
+/// Tests that the number of in-flight requests never exceeds the
+/// concurrency limit.
+#[test]
+fn test_fill_in_flight() {
+    let client = Client::new();
+    let url = Url::parse("https://www.enhance.com/").unwrap();
+    let config = CrawlConfig {
+        concurrency: 2,
+        respect_robots_txt: false,
+        ..CrawlConfig::default()
+    };
+    let mut crawl_state =
+        CrawlStreamState::create(Arc::new(client), Arc::new(config), Arc::new(()), url);
+    for page in &["a.html", "b.html", "c.html"] {
+        let url = Url::parse("https://www.enhance.com/").unwrap();
+        let _ = crawl_state.add_url_to_queue(url.join(page).unwrap(), 1, None);
+    }
+    crawl_state.fill_in_flight();
+    assert_eq!(crawl_state.in_flight.len(), 2);
+    assert_eq!(crawl_state.visited.len(), 2);
+    assert_eq!(crawl_state.to_visit.len(), 2);
+}
+
+/// Tests that sitemaps are requested within the concurrency limit, and that
+/// a crawl queues at most `MAX_SITEMAPS` of them.
+#[test]
+fn test_fill_in_flight_sitemaps() {
+    let config = CrawlConfig {
+        concurrency: 2,
+        respect_robots_txt: false,
+        ..CrawlConfig::default()
+    };
+    let mut crawl_state = CrawlStreamState::create(
+        Arc::new(Client::new()),
+        Arc::new(config),
+        Arc::new(()),
+        url("/"),
+    );
+    for i in 0..=MAX_SITEMAPS {
+        crawl_state.queue_sitemap(url(&format!("/sitemap-{}.xml", i)));
+    }
+    assert_eq!(crawl_state.sitemap_queue.len(), MAX_SITEMAPS);
+    crawl_state.fill_in_flight();
+    assert_eq!(crawl_state.in_flight.len(), 2);
+    assert_eq!(crawl_state.sitemap_queue.len(), MAX_SITEMAPS - 2);
+    assert_eq!(crawl_state.to_visit.len(), 1);
+}
+
+/// Tests a crawl configured through the builder stops at its page limit.
+#[tokio::test]
+async fn test_crawl_max_pages() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let crawler = CrawlerBuilder::new()
+        .max_pages(2)
+        .user_agent("web_crawler_lib-test")
+        .build()
+        .unwrap();
+    let count = crawler.unique_url_count(mock_url("simple.html")).await;
+    assert_eq!(count, 2);
+}
+
+/// Tests a crawl configured through the builder does not follow filtered
+/// URLs.
+#[tokio::test]
+async fn test_crawl_filter() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let crawler = CrawlerBuilder::new()
+        .filter(|url| url.path() != "/self_ref.html")
+        .build()
+        .unwrap();
+    let list = crawler.unique_url_list(mock_url("simple.html")).await;
+    assert_eq!(list.len(), 2);
+    assert!(!list.contains(&mock_url("self_ref.html")));
+}
+
+/// Tests that URLs disallowed by `robots.txt` are reported as skipped rather
+/// than requested.
+#[tokio::test]
+async fn test_crawl_robots_disallow() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = mock("GET", "/robots.txt")
+        .with_status(200)
+        .with_body("User-agent: *\nDisallow: /self_ref\n")
+        .create();
+    let _m5 = mock("GET", "/self_ref.html").expect(0).create();
 
     let client = Client::new();
     let url = mock_url("simple.html");
 
-    use std::vec::Vec;
-    use stream::StreamExt;
     let results: Vec<CrawlResult> = crawl_domain_with_client(client, url).collect().await;
-    assert_eq!(results.iter().count(), 3);
+    assert_eq!(results.len(), 3);
+    let skipped: Vec<&CrawlResult> = results.iter().filter(|r| !r.is_visited()).collect();
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].url, mock_url("self_ref.html"));
+    assert_eq!(
+        skipped[0].outcome,
+        CrawlOutcome::Skipped(SkipReason::RobotsTxt)
+    );
+    _m5.assert();
+}
+
+/// Tests that an unreachable `robots.txt` disallows the whole site.
+#[tokio::test]
+async fn test_crawl_robots_unavailable() {
+    let _m1 = simple_html();
+    let _m2 = mock("GET", "/robots.txt").with_status(503).create();
+
+    let client = Client::new();
+    let count = unique_url_count_with_client(client, mock_url("simple.html")).await;
+    assert_eq!(count, 0);
+}
+
+/// Tests a starting URL that cannot have a `robots.txt` file, or sitemaps,
+/// is requested and reported like any other.
+#[tokio::test]
+async fn test_crawl_opaque_origin() {
+    let start = Url::parse("mailto:a@b.com").unwrap();
+    let builder = CrawlerBuilder::new()
+        .fetcher(MemoryFetcher::new())
+        .sitemaps(true);
+    let results = crawl(builder, start.clone()).await;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].url, start);
+    assert_eq!(
+        results[0].outcome,
+        CrawlOutcome::Failed(Error::HttpStatus(StatusCode::NOT_FOUND))
+    );
+}
+
+/// Tests that a `Crawl-delay` spaces out requests to the same origin.
+#[tokio::test]
+async fn test_crawl_robots_crawl_delay() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = mock("GET", "/robots.txt")
+        .with_status(200)
+        .with_body("User-agent: *\nCrawl-delay: 0.1\n")
+        .create();
+
+    let client = Client::new();
+    let start = Instant::now();
+    let count = unique_url_count_with_client(client, mock_url("simple.html")).await;
+    assert_eq!(count, 3);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+/// Returns a fetcher serving a home page linking to `/public.html` and
+/// `/private.html`, and a `robots.txt` that disallows `/private.html` behind
+/// `hops` redirects.
+fn redirected_robots_fetcher(hops: usize) -> MemoryFetcher {
+    let mut fetcher = MemoryFetcher::new()
+        .with_page(
+            url("/"),
+            "text/html",
+            r#"<a href="/public.html">Public</a><a href="/private.html">Private</a>"#,
+        )
+        .with_page(url("/public.html"), "text/html", "<p>Public</p>")
+        .with_page(url("/private.html"), "text/html", "<p>Private</p>");
+    let mut from = url("/robots.txt");
+    for hop in 0..hops {
+        let to = url(&format!("/robots-{}.txt", hop));
+        fetcher = fetcher.with_redirect(from, &to);
+        from = to;
+    }
+    fetcher.with_page(
+        from,
+        "text/plain",
+        "User-agent: *\nDisallow: /private.html\n",
+    )
+}
+
+/// Tests the rules of a redirected `robots.txt` are obeyed.
+#[tokio::test]
+async fn test_crawl_robots_redirect() {
+    let builder = CrawlerBuilder::new().fetcher(redirected_robots_fetcher(5));
+    let mut results = crawl(builder, url("/")).await;
+    results.sort_by(|a, b| a.url.cmp(&b.url));
+    let outcomes: Vec<(&str, &CrawlOutcome)> =
+        results.iter().map(|r| (r.url.path(), &r.outcome)).collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            ("/", &CrawlOutcome::Visited),
+            (
+                "/private.html",
+                &CrawlOutcome::Skipped(SkipReason::RobotsTxt)
+            ),
+            ("/public.html", &CrawlOutcome::Visited),
+        ]
+    );
+}
+
+/// Tests a `robots.txt` behind more redirects than RFC 9309 asks crawlers to
+/// follow is treated as unavailable, allowing everything.
+#[tokio::test]
+async fn test_crawl_robots_too_many_redirects() {
+    let crawler = CrawlerBuilder::new()
+        .fetcher(redirected_robots_fetcher(6))
+        .build()
+        .unwrap();
+    let url = Url::parse("https://www.enhance.com/").unwrap();
+    assert_eq!(crawler.unique_url_count(url).await, 3);
+}
+
+/// Tests only the first `MAX_ROBOTS_SIZE` bytes of a `robots.txt` are obeyed,
+/// leaving out a rule cut off at the limit.
+#[tokio::test]
+async fn test_crawl_robots_too_large() {
+    let mut robots = "User-agent: *\nDisallow: /private.html\n".to_owned();
+    // A comment ending 8 bytes short of the limit, which cuts off the line
+    // after it.
+    let padding = MAX_ROBOTS_SIZE as usize - 8 - robots.len() - 2;
+    robots.push_str(&format!("#{}\n", "x".repeat(padding)));
+    robots.push_str("Disallow: /public.html\n");
+    let fetcher = MemoryFetcher::new()
+        .with_page(
+            url("/"),
+            "text/html",
+            r#"<a href="/public.html">Public</a><a href="/private.html">Private</a>"#,
+        )
+        .with_page(url("/public.html"), "text/html", "<p>Public</p>")
+        .with_page(url("/private.html"), "text/html", "<p>Private</p>")
+        .with_page(url("/robots.txt"), "text/plain", robots);
+    let mut results = crawl(CrawlerBuilder::new().fetcher(fetcher), url("/")).await;
+    results.sort_by(|a, b| a.url.cmp(&b.url));
+    let outcomes: Vec<(&str, &CrawlOutcome)> =
+        results.iter().map(|r| (r.url.path(), &r.outcome)).collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            ("/", &CrawlOutcome::Visited),
+            (
+                "/private.html",
+                &CrawlOutcome::Skipped(SkipReason::RobotsTxt)
+            ),
+            ("/public.html", &CrawlOutcome::Visited),
+        ]
+    );
+}
+
+/// Tests a redirected sitemap is read.
+#[tokio::test]
+async fn test_crawl_sitemap_redirect() {
+    let sitemap = format!(
+        r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"><url><loc>{}</loc></url></urlset>"#,
+        url("/orphan.html")
+    );
+    let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), "text/html", "<p>Home</p>")
+        .with_page(url("/orphan.html"), "text/html", "<p>Orphan</p>")
+        .with_redirect(url("/sitemap.xml"), &url("/sitemaps/pages.xml"))
+        .with_page(url("/sitemaps/pages.xml"), "application/xml", sitemap);
+    let crawler = CrawlerBuilder::new()
+        .fetcher(fetcher)
+        .sitemaps(true)
+        .build()
+        .unwrap();
+    assert_eq!(crawler.unique_url_count(url("/")).await, 2);
+}
+
+/// Mocks a page at `path` that is not linked to from any other page.
+fn orphan_html(path: &str) -> Mock {
+    mock("GET", path)
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body("<!DOCTYPE html><html><body></body></html>")
+        .create()
+}
+
+/// Tests pages are found through a sitemap index listed in `robots.txt` that
+/// points at a gzip'd sitemap.
+#[tokio::test]
+async fn test_crawl_sitemaps_from_robots() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = mock("GET", "/robots.txt")
+        .with_status(200)
+        .with_body(format!("Sitemap: {}\n", mock_url("index.xml")))
+        .create();
+    let _m5 = mock("GET", "/index.xml")
+        .with_status(200)
+        .with_body(format!(
+            r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"><sitemap><loc>{}</loc></sitemap></sitemapindex>"#,
+            mock_url("pages.xml.gz")
+        ))
+        .create();
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    write!(
+        encoder,
+        r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"><url><loc>{}</loc></url><url><loc>https://www.enhance.com/</loc></url></urlset>"#,
+        mock_url("orphan.html")
+    )
+    .unwrap();
+    let _m6 = mock("GET", "/pages.xml.gz")
+        .with_status(200)
+        .with_body(encoder.finish().unwrap())
+        .create();
+    let _m7 = orphan_html("/orphan.html");
+
+    let crawler = CrawlerBuilder::new().sitemaps(true).build().unwrap();
+    let list = crawler.unique_url_list(mock_url("simple.html")).await;
+    assert_eq!(list.len(), 4);
+    assert!(list.contains(&mock_url("orphan.html")));
+}
+
+/// Tests pages are found through `/sitemap.xml` when `robots.txt` is not
+/// used, and that sitemaps are not read unless asked for.
+#[tokio::test]
+async fn test_crawl_sitemap_xml() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = mock("GET", "/sitemap.xml")
+        .with_status(200)
+        .with_body(format!(
+            r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"><url><loc>{}</loc></url></urlset>"#,
+            mock_url("orphan.html")
+        ))
+        .create();
+    let _m5 = orphan_html("/orphan.html");
+
+    let crawler = CrawlerBuilder::new()
+        .respect_robots_txt(false)
+        .sitemaps(true)
+        .build()
+        .unwrap();
+    let count = crawler.unique_url_count(mock_url("simple.html")).await;
+    assert_eq!(count, 4);
+
+    let crawler = CrawlerBuilder::new()
+        .respect_robots_txt(false)
+        .build()
+        .unwrap();
+    let count = crawler.unique_url_count(mock_url("simple.html")).await;
+    assert_eq!(count, 3);
+}
+
+/// Tests the sitemaps a sitemap index lists are only requested when in scope
+/// and allowed by `robots.txt`.
+#[tokio::test]
+async fn test_crawl_sitemap_index_scope() {
+    let index = format!(
+        r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+        <sitemap><loc>{}</loc></sitemap>
+        <sitemap><loc>https://www.google.com/sitemap.xml</loc></sitemap>
+        <sitemap><loc>{}</loc></sitemap>
+        </sitemapindex>"#,
+        url("/pages.xml"),
+        url("/private/pages.xml"),
+    );
+    let urlset = |path| {
+        format!(
+            r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"><url><loc>{}</loc></url></urlset>"#,
+            url(path)
+        )
+    };
+    let fetcher = MemoryFetcher::new()
+        .with_page(
+            url("/robots.txt"),
+            "text/plain",
+            format!(
+                "User-agent: *\nDisallow: /private/\nSitemap: {}\n",
+                url("/index.xml")
+            ),
+        )
+        .with_page(url("/"), "text/html", "<p>Home</p>")
+        .with_page(url("/index.xml"), "application/xml", index)
+        .with_page(url("/pages.xml"), "application/xml", urlset("/orphan.html"))
+        .with_page(url("/orphan.html"), "text/html", "<p>Orphan</p>");
+    let builder = CrawlerBuilder::new().sitemaps(true).concurrency(1);
+    let (results, requests) = recorded_crawl(builder, fetcher).await;
+    let urls: Vec<&Url> = results.iter().map(|r| &r.url).collect();
+    assert_eq!(urls, vec![&url("/"), &url("/orphan.html")]);
+    assert!(requests.contains(&url("/pages.xml")));
+    assert!(!requests.contains(&url("/private/pages.xml")));
+    assert!(requests
+        .iter()
+        .all(|url| url.host_str() == Some("www.enhance.com")));
+}
+
+/// Tests a sitemap larger than the protocol allows is not read.
+#[tokio::test]
+async fn test_crawl_sitemap_too_large() {
+    use reqwest::header::CONTENT_LENGTH;
+
+    let sitemap = format!(
+        r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"><url><loc>{}</loc></url></urlset>"#,
+        url("/orphan.html")
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(MAX_SITEMAP_SIZE + 1));
+    let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), "text/html", "<p>Home</p>")
+        .with_page(url("/orphan.html"), "text/html", "<p>Orphan</p>")
+        .with_response(url("/sitemap.xml"), StatusCode::OK, headers, sitemap);
+    let crawler = CrawlerBuilder::new()
+        .fetcher(fetcher)
+        .sitemaps(true)
+        .build()
+        .unwrap();
+    assert_eq!(crawler.unique_url_list(url("/")).await, vec![url("/")]);
+}
+
+/// Tests that crawls given clones of the same client share its host limits.
+#[tokio::test]
+async fn test_crawl_shared_client_limits() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let limiter = RateLimiter::new(HostLimits {
+        min_delay: Duration::from_millis(50),
+        ..HostLimits::default()
+    });
+    let client = RateLimitedFetcher::new(no_redirect_client(), limiter);
+    let url = mock_url("simple.html");
+    let start = Instant::now();
+    let (first, second) = futures::join!(
+        unique_url_count_with_client(client.clone(), url.clone()),
+        unique_url_count_with_client(client, url)
+    );
+    assert_eq!((first, second), (3, 3));
+    // As with a shared crawler, eight requests at least 50ms apart.
+    assert!(start.elapsed() >= Duration::from_millis(350));
+}
+
+/// Tests that crawls run by the same crawler share its host limits.
+#[tokio::test]
+async fn test_crawl_shared_host_limits() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let crawler = CrawlerBuilder::new()
+        .host_limits(HostLimits {
+            min_delay: Duration::from_millis(50),
+            ..HostLimits::default()
+        })
+        .build()
+        .unwrap();
+    let url = mock_url("simple.html");
+    let start = Instant::now();
+    let (first, second) = futures::join!(
+        crawler.unique_url_count(url.clone()),
+        crawler.unique_url_count(url)
+    );
+    assert_eq!((first, second), (3, 3));
+    // Two crawls make eight requests between them, including `robots.txt`,
+    // each at least 50ms after the last.
+    assert!(start.elapsed() >= Duration::from_millis(350));
+}
+
+/// Tests that URLs are canonicalized before being queued, so that variants
+/// of a visited URL are not queued again.
+#[test]
+fn test_add_canonical_url_to_queue() {
+    let mut crawl_state = default_state();
+    let url = Url::parse("https://www.enhance.com/%7eteam/#content").unwrap();
+    let _ = crawl_state.add_url_to_queue(url.clone(), 1, None);
+    let queued = crawl_state.to_visit.iter().find(|q| q.depth == 1).unwrap();
+    assert_eq!(queued.url.as_str(), "https://www.enhance.com/~team/");
+    assert_eq!(queued.original, url);
+
+    crawl_state.visited.insert(queued.url.clone());
+    let variant = Url::parse("https://www.enhance.com/~team/#top").unwrap();
+    assert_eq!(crawl_state.add_url_to_queue(variant, 1, None), None);
+}
+
+/// Tests that shallower URLs are taken from the to-visit queue first.
+#[test]
+fn test_queue_depth_order() {
+    let mut crawl_state = default_state();
+    let url = Url::parse("https://www.enhance.com/").unwrap();
+    let _ = crawl_state.add_url_to_queue(url.join("a.html").unwrap(), 2, None);
+    let _ = crawl_state.add_url_to_queue(url.join("b.html").unwrap(), 1, None);
+    let depths: Vec<usize> = std::iter::from_fn(|| crawl_state.to_visit.pop())
+        .map(|q| q.depth)
+        .collect();
+    assert_eq!(depths, vec![0, 1, 2]);
+}
+
+/// Tests each result reports the number of clicks from the starting URL.
+#[tokio::test]
+async fn test_crawl_depth() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let client = Client::new();
+    let url = mock_url("simple.html");
+
+    let mut depths: Vec<(Url, usize)> = crawl_domain_with_client(client, url)
+        .map(|r| (r.url, r.depth))
+        .collect()
+        .await;
+    depths.sort_by_key(|(_, depth)| *depth);
+    assert_eq!(
+        depths,
+        vec![
+            (mock_url("simple.html"), 0),
+            (mock_url("link_node.html"), 1),
+            (mock_url("self_ref.html"), 2),
+        ]
+    );
+}
+
+/// Tests each result reports the page it was first found on.
+#[tokio::test]
+async fn test_crawl_referrer() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let client = Client::new();
+    let url = mock_url("simple.html");
+
+    let mut referrers: Vec<(Url, Option<Url>)> = crawl_domain_with_client(client, url)
+        .map(|r| (r.url, r.referrer))
+        .collect()
+        .await;
+    referrers.sort();
+    assert_eq!(
+        referrers,
+        vec![
+            (mock_url("link_node.html"), Some(mock_url("simple.html"))),
+            (mock_url("self_ref.html"), Some(mock_url("link_node.html"))),
+            (mock_url("simple.html"), None),
+        ]
+    );
+}
+
+/// Tests a request that fails reports why, and has no response.
+#[tokio::test]
+async fn test_pop_failure() {
+    let url = Url::parse("https://foo.invalid/failure.html").unwrap();
+    let error = Error::Dns("failed to lookup address information".to_owned());
+    let fetcher = MemoryFetcher::new().with_error(url.clone(), error);
+    let config = CrawlConfig {
+        respect_robots_txt: false,
+        ..CrawlConfig::default()
+    };
+    let crawl_state =
+        CrawlStreamState::create(Arc::new(fetcher), Arc::new(config), Arc::new(()), url);
+    let (result, _) = crawl_state.pop_document().await.unwrap();
+    assert!(result.is_visited());
+    assert!(matches!(result.error(), Some(Error::Dns(_))));
+    assert!(result.response.is_none());
+}
+
+/// Tests a page with an error status fails, and its links are not followed.
+#[tokio::test]
+async fn test_pop_error_status() {
+    let _m = mock("GET", "/missing.html")
+        .with_status(404)
+        .with_body(include_str!("../res/simple.html"))
+        .create();
+    let _r = robots_txt();
+
+    let client = Client::new();
+    let url = mock_url("missing.html");
+    let crawl_state =
+        CrawlStreamState::create(Arc::new(client), Default::default(), Arc::new(()), url);
+    let (result, state) = crawl_state.pop_document().await.unwrap();
+    assert_eq!(
+        result.outcome,
+        CrawlOutcome::Failed(Error::HttpStatus(StatusCode::NOT_FOUND))
+    );
+    assert_eq!(result.response.unwrap().status, StatusCode::NOT_FOUND);
+    assert!(state.to_visit.is_empty());
+}
+
+/// Tests failing URLs are reported with the pages linking to them.
+#[tokio::test]
+async fn test_crawl_broken_links() {
+    let _m1 = mock("GET", "/index.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<a href="/gone.html">Old news</a><a href="/error.html">Status</a>"#)
+        .create();
+    let _m2 = mock("GET", "/gone.html").with_status(410).create();
+    let _m3 = mock("GET", "/error.html").with_status(503).create();
+    let _m4 = robots_txt();
+
+    let crawler = CrawlerBuilder::new().build().unwrap();
+    let mut broken = crawler.broken_links(mock_url("index.html")).await;
+    broken.sort_by(|a, b| a.url.cmp(&b.url));
+    let summary: Vec<(Url, Error, Url, Option<String>)> = broken
+        .into_iter()
+        .map(|mut b| {
+            assert_eq!(b.referrers.len(), 1);
+            let referrer = b.referrers.remove(0);
+            (b.url, b.error, referrer.page, referrer.text)
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (
+                mock_url("error.html"),
+                Error::HttpStatus(StatusCode::SERVICE_UNAVAILABLE),
+                mock_url("index.html"),
+                Some("Status".to_owned())
+            ),
+            (
+                mock_url("gone.html"),
+                Error::HttpStatus(StatusCode::GONE),
+                mock_url("index.html"),
+                Some("Old news".to_owned())
+            ),
+        ]
+    );
+}
+
+/// Tests a page larger than the maximum body size fails.
+#[tokio::test]
+async fn test_pop_too_large() {
+    let _m = simple_html();
+    let _r = robots_txt();
+
+    let client = Client::new();
+    let config = CrawlConfig {
+        max_body_size: Some(16),
+        ..CrawlConfig::default()
+    };
+    let crawl_state = CrawlStreamState::create(
+        Arc::new(client),
+        Arc::new(config),
+        Arc::new(()),
+        mock_url("simple.html"),
+    );
+    let (result, state) = crawl_state.pop_document().await.unwrap();
+    assert_eq!(result.error(), Some(&Error::TooLarge(16)));
+    assert!(state.to_visit.is_empty());
+}
+
+/// Tests links beyond the maximum depth are not followed.
+#[tokio::test]
+async fn test_crawl_max_depth() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let url = mock_url("simple.html");
+    let crawler = CrawlerBuilder::new().max_depth(0).build().unwrap();
+    assert_eq!(
+        crawler.unique_url_list(url.clone()).await,
+        vec![url.clone()]
+    );
+
+    let crawler = CrawlerBuilder::new().max_depth(1).build().unwrap();
+    assert_eq!(crawler.unique_url_count(url).await, 2);
+}
+
+/// Tests a crawl that visits everything in scope reports no budget.
+#[tokio::test]
+async fn test_crawl_no_budget_exhausted() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let crawler = CrawlerBuilder::new().max_pages(3).build().unwrap();
+    let mut stream = crawler.crawl(mock_url("simple.html"));
+    let mut count = 0;
+    while stream.next().await.is_some() {
+        count += 1;
+    }
+    assert_eq!(count, 3);
+    assert_eq!(stream.budget_exhausted(), None);
+}
+
+/// Tests a crawl ends, and reports why, when its page budget is used up.
+#[tokio::test]
+async fn test_crawl_page_budget() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let crawler = CrawlerBuilder::new().max_pages(2).build().unwrap();
+    let mut stream = crawler.crawl(mock_url("simple.html"));
+    let mut count = 0;
+    while stream.next().await.is_some() {
+        count += 1;
+    }
+    assert_eq!(count, 2);
+    assert_eq!(stream.budget_exhausted(), Some(Budget::Pages));
+}
+
+/// Tests a crawl ends, and reports why, when its byte budget is used up.
+#[tokio::test]
+async fn test_crawl_byte_budget() {
+    let _m1 = simple_html();
+    let _m2 = self_ref_html();
+    let _m3 = link_node_html();
+    let _m4 = robots_txt();
+
+    let crawler = CrawlerBuilder::new().max_bytes(1).build().unwrap();
+    let mut stream = crawler.crawl(mock_url("simple.html"));
+    let mut count = 0;
+    while stream.next().await.is_some() {
+        count += 1;
+    }
+    assert_eq!(count, 1);
+    assert_eq!(stream.budget_exhausted(), Some(Budget::Bytes));
+}
+
+/// Tests a crawl ends, and reports why, when its time budget is used up.
+#[tokio::test]
+async fn test_crawl_duration_budget() {
+    let _m1 = simple_html();
+
+    let crawler = CrawlerBuilder::new()
+        .max_duration(Duration::from_secs(0))
+        .build()
+        .unwrap();
+    let mut stream = crawler.crawl(mock_url("simple.html"));
+    assert!(stream.next().await.is_none());
+    assert_eq!(stream.budget_exhausted(), Some(Budget::Duration));
 }
 
 /// Tests the vector of URLs generated from the stream.
@@ -149,11 +1533,12 @@ async fn unique_url_list() {
     let _m1 = simple_html();
     let _m2 = self_ref_html();
     let _m3 = link_node_html();
+    let _m4 = robots_txt();
 
     let client = Client::new();
     let url = mock_url("simple.html");
     let list = unique_url_list_with_client(client, url).await;
-    assert_eq!(list.iter().count(), 3);
+    assert_eq!(list.len(), 3);
 }
 
 /// Tests the count of URLs generated from the stream.
@@ -162,9 +1547,244 @@ async fn unique_url_count() {
     let _m1 = simple_html();
     let _m2 = self_ref_html();
     let _m3 = link_node_html();
+    let _m4 = robots_txt();
 
     let client = Client::new();
     let url = mock_url("simple.html");
     let count = unique_url_count_with_client(client, url).await;
     assert_eq!(count, 3);
 }
+
+/// Mocks a redirect from `path` to `location`.
+fn redirect(path: &str, location: &str) -> Mock {
+    mock("GET", path)
+        .with_status(301)
+        .with_header("location", location)
+        .create()
+}
+
+/// Creates a client that leaves redirects for the crawl to follow.
+fn no_redirect_client() -> Client {
+    Client::builder().redirect(Policy::none()).build().unwrap()
+}
+
+/// Tests a redirected page records each hop, is resolved against its final
+/// URL, and that URL is not crawled again.
+#[tokio::test]
+async fn test_crawl_redirect() {
+    let _m1 = redirect("/old/moved.html", "/hop.html");
+    let _m2 = redirect("/hop.html", "/docs/index.html");
+    let _m3 = mock("GET", "/docs/index.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<a href="page.html">Page</a><a href="/docs/index.html">Self</a>"#)
+        .create();
+    let _m4 = orphan_html("/docs/page.html");
+    let _m5 = robots_txt();
+
+    let results: Vec<CrawlResult> =
+        crawl_domain_with_client(no_redirect_client(), mock_url("old/moved.html"))
+            .collect()
+            .await;
+    let urls: Vec<&Url> = results.iter().map(|r| &r.url).collect();
+    assert_eq!(
+        urls,
+        vec![&mock_url("old/moved.html"), &mock_url("docs/page.html")]
+    );
+
+    let response = results[0].response.as_ref().unwrap();
+    assert_eq!(response.final_url, mock_url("docs/index.html"));
+    assert_eq!(
+        response.redirects,
+        vec![
+            Redirect {
+                from: mock_url("old/moved.html"),
+                status: StatusCode::MOVED_PERMANENTLY,
+                to: mock_url("hop.html"),
+            },
+            Redirect {
+                from: mock_url("hop.html"),
+                status: StatusCode::MOVED_PERMANENTLY,
+                to: mock_url("docs/index.html"),
+            },
+        ]
+    );
+}
+
+/// A fetcher recording the URL of every request it passes on.
+struct RecordingFetcher {
+    /// The fetcher requests are passed on to.
+    inner: MemoryFetcher,
+    /// The URL of every request made, in order.
+    requests: Arc<std::sync::Mutex<Vec<Url>>>,
+}
+
+impl Fetcher for RecordingFetcher {
+    fn fetch(
+        &self,
+        request: FetchRequest,
+    ) -> BoxFuture<'static, std::result::Result<FetchResponse, Error>> {
+        self.requests.lock().unwrap().push(request.url.clone());
+        self.inner.fetch(request)
+    }
+}
+
+/// Crawls from `/` of `fetcher`'s site with a crawler built by `builder`,
+/// returning the results by URL, along with every URL requested.
+async fn recorded_crawl(
+    builder: CrawlerBuilder,
+    fetcher: MemoryFetcher,
+) -> (Vec<CrawlResult>, Vec<Url>) {
+    let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let fetcher = RecordingFetcher {
+        inner: fetcher,
+        requests: requests.clone(),
+    };
+    let mut results = crawl(builder.fetcher(fetcher), url("/")).await;
+    results.sort_by(|a, b| a.url.cmp(&b.url));
+    let requests = requests.lock().unwrap().clone();
+    (results, requests)
+}
+
+/// Tests a redirect to a URL `robots.txt` disallows is not followed.
+#[tokio::test]
+async fn test_crawl_redirect_disallowed() {
+    let fetcher = MemoryFetcher::new()
+        .with_page(
+            url("/robots.txt"),
+            "text/plain",
+            "User-agent: *\nDisallow: /private/\n",
+        )
+        .with_page(url("/"), "text/html", r#"<a href="/go.html">Go</a>"#)
+        .with_redirect(url("/go.html"), &url("/private/page.html"))
+        .with_page(url("/private/page.html"), "text/html", "<p>Private</p>");
+    let (results, requests) = recorded_crawl(CrawlerBuilder::new(), fetcher).await;
+    assert_eq!(
+        requests,
+        vec![url("/robots.txt"), url("/"), url("/go.html")]
+    );
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[1].url, url("/go.html"));
+    assert_eq!(
+        results[1].outcome,
+        CrawlOutcome::Skipped(SkipReason::RobotsTxt)
+    );
+    let response = results[1].response.as_ref().unwrap();
+    assert_eq!(response.final_url, url("/go.html"));
+    assert_eq!(response.redirects[0].to, url("/private/page.html"));
+}
+
+/// Tests a redirect to a URL that has already been visited is not followed,
+/// so the URL is not requested again.
+#[tokio::test]
+async fn test_crawl_redirect_visited() {
+    let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), "text/html", r#"<a href="/home.html">Home</a>"#)
+        .with_redirect(url("/home.html"), &url("/"));
+    let (results, requests) = recorded_crawl(CrawlerBuilder::new(), fetcher).await;
+    assert_eq!(
+        requests,
+        vec![url("/robots.txt"), url("/"), url("/home.html")]
+    );
+    let outcomes: Vec<(&str, &CrawlOutcome)> =
+        results.iter().map(|r| (r.url.path(), &r.outcome)).collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            ("/", &CrawlOutcome::Visited),
+            ("/home.html", &CrawlOutcome::Visited)
+        ]
+    );
+    let response = results[1].response.as_ref().unwrap();
+    assert_eq!(response.status, StatusCode::MOVED_PERMANENTLY);
+    assert!(results[1].links.is_empty());
+}
+
+/// Tests URLs are requested as they were found, rather than in their
+/// canonical form, and a redirect adding a trailing slash is followed even
+/// though it leads to the same canonical URL.
+#[tokio::test]
+async fn test_crawl_trailing_slash_redirect() {
+    let fetcher = MemoryFetcher::new()
+        .with_page(
+            url("/"),
+            "text/html",
+            r#"<a href="/docs/">Docs</a><a href="/guide">Guide</a>"#,
+        )
+        .with_redirect(url("/docs"), &url("/docs/"))
+        .with_page(
+            url("/docs/"),
+            "text/html",
+            r#"<a href="intro.html">Intro</a>"#,
+        )
+        .with_redirect(url("/guide"), &url("/guide/"))
+        .with_page(
+            url("/guide/"),
+            "text/html",
+            r#"<a href="start.html">Start</a>"#,
+        )
+        .with_page(url("/docs/intro.html"), "text/html", "<p>Intro</p>")
+        .with_page(url("/guide/start.html"), "text/html", "<p>Start</p>");
+    let builder = CrawlerBuilder::new().canonicalizer(Canonicalizer::new(vec![
+        Canonicalize::StripFragment,
+        Canonicalize::StripTrailingSlash,
+    ]));
+    let (results, requests) = recorded_crawl(builder, fetcher).await;
+    assert!(requests.contains(&url("/docs/")));
+    assert!(!requests.contains(&url("/docs")));
+    assert!(requests.contains(&url("/guide/")));
+    let visited: Vec<(&str, usize)> = results
+        .iter()
+        .map(|r| (r.url.path(), r.links.len()))
+        .collect();
+    assert_eq!(
+        visited,
+        vec![
+            ("/", 2),
+            ("/docs", 1),
+            ("/docs/intro.html", 0),
+            ("/guide", 1),
+            ("/guide/start.html", 0),
+        ]
+    );
+    assert!(results.iter().all(|r| r.outcome == CrawlOutcome::Visited));
+}
+
+/// Tests a redirect out of scope is not followed.
+#[tokio::test]
+async fn test_crawl_redirect_out_of_scope() {
+    let _m1 = redirect("/away.html", "https://www.enhance.com/");
+    let _m2 = robots_txt();
+
+    let results: Vec<CrawlResult> =
+        crawl_domain_with_client(no_redirect_client(), mock_url("away.html"))
+            .collect()
+            .await;
+    assert_eq!(results.len(), 1);
+    assert_eq!(
+        results[0].outcome,
+        CrawlOutcome::Skipped(SkipReason::RedirectOutOfScope)
+    );
+    let response = results[0].response.as_ref().unwrap();
+    assert_eq!(
+        response.redirects[0].to.as_str(),
+        "https://www.enhance.com/"
+    );
+}
+
+/// Tests a redirect loop fails rather than being followed forever.
+#[tokio::test]
+async fn test_crawl_redirect_loop() {
+    let _m1 = redirect("/loop_a.html", "/loop_b.html");
+    let _m2 = redirect("/loop_b.html", "/loop_a.html");
+    let _m3 = robots_txt();
+
+    let results: Vec<CrawlResult> =
+        crawl_domain_with_client(no_redirect_client(), mock_url("loop_a.html"))
+            .collect()
+            .await;
+    assert_eq!(
+        results[0].error(),
+        Some(&Error::RedirectLoop(mock_url("loop_a.html")))
+    );
+}
@@ -0,0 +1,654 @@
+//! Archiving of the requests and responses of a crawl as WARC files.
+use crate::{FetchRequest, ResponseInfo};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::{HeaderMap, HOST, TRANSFER_ENCODING};
+use reqwest::Version;
+use sha1::{Digest, Sha1};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::task;
+use uuid::Uuid;
+
+/// The size at which a WARC file is closed and another begun, unless
+/// configured otherwise: 1 GB, as the WARC standard recommends.
+pub const DEFAULT_MAX_WARC_SIZE: u64 = 1_000_000_000;
+
+/// How a `WarcWriter` names and splits the files it writes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WarcOptions {
+    /// The start of the name of every file. Each name goes on with the time
+    /// the file was begun and a serial number, as in
+    /// `crawl-20240101120000-00000.warc.gz`.
+    pub prefix: String,
+    /// The size in bytes past which a file is closed, and the next record
+    /// begins another, if any. A single exchange larger than this still goes
+    /// in one file.
+    pub max_file_size: Option<u64>,
+    /// Whether each record is compressed as a gzip member of its own, giving
+    /// `.warc.gz` files, rather than written as is to `.warc` files.
+    pub gzip: bool,
+}
+
+impl Default for WarcOptions {
+    fn default() -> Self {
+        Self {
+            prefix: "crawl".to_owned(),
+            max_file_size: Some(DEFAULT_MAX_WARC_SIZE),
+            gzip: true,
+        }
+    }
+}
+
+/// Why the body of a response was not archived in full, given in the
+/// `WARC-Truncated` field of its record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Truncated {
+    /// The body was larger than the configured maximum.
+    Length,
+    /// The body took longer to read than the configured timeout.
+    Time,
+    /// The connection failed before the body was read in full.
+    Disconnect,
+    /// The body was not read, such as a resource that is not HTML.
+    Unspecified,
+}
+
+impl Truncated {
+    /// The value of the `WARC-Truncated` field.
+    fn as_str(self) -> &'static str {
+        match self {
+            Truncated::Length => "length",
+            Truncated::Time => "time",
+            Truncated::Disconnect => "disconnect",
+            Truncated::Unspecified => "unspecified",
+        }
+    }
+}
+
+/// Writes the requests and responses of crawls into WARC 1.1 files, keeping
+/// the bytes of every body the crawler read.
+///
+/// Each exchange is archived as a `request` record followed by a `response`
+/// record, dated when the request was sent, and every file begins with a
+/// `warcinfo` record. Every record has a SHA-1 `WARC-Block-Digest`, and
+/// responses a `WARC-Payload-Digest` of their body. Bodies the crawler did
+/// not read, or did not read in full, are marked with `WARC-Truncated`.
+///
+/// The bytes of HTTP messages are not kept as they crossed the wire, so they
+/// are reconstructed from the request sent and the response headers
+/// received. Both start lines give the HTTP version of the response, and the
+/// response's the standard reason phrase for its status. The client does not
+/// keep the case of header names, so they are written as HTTP/2 and later
+/// send them, in lower case, and otherwise in title case, as in
+/// `Content-Type`, which is how crawlers built by `CrawlerBuilder` send
+/// them. `Transfer-Encoding` is left out, as bodies are kept as delivered
+/// after any chunking.
+///
+/// Files are written on tokio's blocking thread pool, so archiving must be
+/// done within a tokio runtime. Clones of a writer share its files, so that
+/// several crawls may archive into the same files.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use web_crawler_lib::{CrawlerBuilder, WarcOptions, WarcWriter};
+/// use reqwest::Url;
+///
+/// #[tokio::main]
+/// pub async fn main() {
+///     let options = WarcOptions {
+///         max_file_size: Some(100 * 1024 * 1024),
+///         ..WarcOptions::default()
+///     };
+///     let crawler = CrawlerBuilder::new()
+///         .warc(WarcWriter::new("archive", options))
+///         .build()
+///         .unwrap();
+///     let url = Url::parse("https://www.linuxmint.com/").unwrap();
+///     crawler.unique_url_count(url).await;
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct WarcWriter {
+    /// The directory files are written to.
+    dir: Arc<PathBuf>,
+    /// How files are named and split.
+    options: Arc<WarcOptions>,
+    /// The file being written, if one has been begun.
+    file: Arc<Mutex<Option<WarcFile>>>,
+}
+
+/// A WARC file being written.
+#[derive(Debug)]
+struct WarcFile {
+    /// The open file.
+    file: File,
+    /// The bytes written to it so far.
+    size: u64,
+    /// The `WARC-Record-ID` of its `warcinfo` record.
+    warcinfo: String,
+}
+
+impl WarcWriter {
+    /// Creates a writer of files named by `options` in `dir`. The directory
+    /// is created, and the first file begun, when the first record is
+    /// written.
+    pub fn new(dir: impl Into<PathBuf>, options: WarcOptions) -> Self {
+        Self {
+            dir: Arc::new(dir.into()),
+            options: Arc::new(options),
+            file: Default::default(),
+        }
+    }
+
+    /// The directory files are written to.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Archives `request`, sent at `date`, and the `response` it had, whose
+    /// body was read as `payload`. `truncated` gives why `payload` is not the
+    /// whole body, if it is not.
+    ///
+    /// Both records go in the same file, which is flushed before returning.
+    /// The file is written on the blocking thread pool, rather than on the
+    /// task awaiting this.
+    pub async fn write_exchange(
+        &self,
+        request: &FetchRequest,
+        response: &ResponseInfo,
+        payload: &[u8],
+        truncated: Option<Truncated>,
+        date: SystemTime,
+    ) -> io::Result<()> {
+        let writer = self.clone();
+        let (request, response, payload) = (request.clone(), response.clone(), payload.to_vec());
+        task::spawn_blocking(move || {
+            writer.write_exchange_blocking(&request, &response, &payload, truncated, date)
+        })
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e)))
+    }
+
+    /// Archives an exchange as `write_exchange` does, blocking the thread.
+    fn write_exchange_blocking(
+        &self,
+        request: &FetchRequest,
+        response: &ResponseInfo,
+        payload: &[u8],
+        truncated: Option<Truncated>,
+        date: SystemTime,
+    ) -> io::Result<()> {
+        let date = UtcTime::from(date).iso8601();
+        let response_id = record_id();
+        let target = ("WARC-Target-URI", request.url.to_string());
+        let mut request_fields = vec![target.clone(), ("WARC-Concurrent-To", response_id.clone())];
+        let mut response_fields = vec![target, ("WARC-Payload-Digest", sha1_digest(payload))];
+        if let Some(truncated) = truncated {
+            response_fields.push(("WARC-Truncated", truncated.as_str().to_owned()));
+        }
+
+        // A poisoned lock only means another exchange failed part way, which
+        // leaves no file open if that one is unusable.
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        let full = match (&*file, self.options.max_file_size) {
+            (Some(current), Some(max)) => current.size >= max,
+            _ => false,
+        };
+        if file.is_none() || full {
+            *file = Some(self.begin_file()?);
+        }
+        // Set just above.
+        let current = file.as_mut().unwrap();
+        let warcinfo = ("WARC-Warcinfo-ID", current.warcinfo.clone());
+        request_fields.push(warcinfo.clone());
+        response_fields.push(warcinfo);
+        let mut records = self.record(
+            "request",
+            &record_id(),
+            &date,
+            &request_fields,
+            "application/http;msgtype=request",
+            &http_request(request, response.version),
+        )?;
+        let mut block = http_response_head(response);
+        block.extend_from_slice(payload);
+        records.extend(self.record(
+            "response",
+            &response_id,
+            &date,
+            &response_fields,
+            "application/http;msgtype=response",
+            &block,
+        )?);
+        let written = current
+            .file
+            .write_all(&records)
+            .and_then(|_| current.file.flush());
+        if let Err(e) = written {
+            // What is in the file is unknown, so it is not written to again.
+            *file = None;
+            return Err(e);
+        }
+        current.size += records.len() as u64;
+        Ok(())
+    }
+
+    /// Creates the next file, and writes its `warcinfo` record.
+    fn begin_file(&self) -> io::Result<WarcFile> {
+        fs::create_dir_all(&*self.dir)?;
+        let extension = if self.options.gzip { "warc.gz" } else { "warc" };
+        let stamp = UtcTime::now().compact();
+        // Files are never overwritten, so the serial number counts up past
+        // those of any files already there, such as from earlier crawls.
+        let (name, file) = (0..)
+            .map(|serial| {
+                let name = format!(
+                    "{}-{}-{:05}.{}",
+                    self.options.prefix, stamp, serial, extension
+                );
+                let path = self.dir.join(&name);
+                (
+                    name,
+                    OpenOptions::new().write(true).create_new(true).open(path),
+                )
+            })
+            .find(|(_, file)| !matches!(file, Err(e) if e.kind() == io::ErrorKind::AlreadyExists))
+            // The range never ends.
+            .unwrap();
+        let mut file = file?;
+        let warcinfo = record_id();
+        let info = format!(
+            "software: web_crawler_lib/{}\r\nformat: WARC File Format 1.1\r\n\
+             conformsTo: http://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        let record = self.record(
+            "warcinfo",
+            &warcinfo,
+            &UtcTime::now().iso8601(),
+            &[("WARC-Filename", name)],
+            "application/warc-fields",
+            info.as_bytes(),
+        )?;
+        file.write_all(&record)?;
+        file.flush()?;
+        Ok(WarcFile {
+            file,
+            size: record.len() as u64,
+            warcinfo,
+        })
+    }
+
+    /// Returns the bytes of a record of type `kind` holding `block`,
+    /// compressed when configured.
+    fn record(
+        &self,
+        kind: &str,
+        id: &str,
+        date: &str,
+        fields: &[(&str, String)],
+        content_type: &str,
+        block: &[u8],
+    ) -> io::Result<Vec<u8>> {
+        let mut record = format!(
+            "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Record-ID: {}\r\nWARC-Date: {}\r\n",
+            kind, id, date
+        );
+        for (name, value) in fields {
+            record.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        record.push_str(&format!("WARC-Block-Digest: {}\r\n", sha1_digest(block)));
+        record.push_str(&format!(
+            "Content-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            content_type,
+            block.len()
+        ));
+        let mut record = record.into_bytes();
+        record.extend_from_slice(block);
+        record.extend_from_slice(b"\r\n\r\n");
+        if !self.options.gzip {
+            return Ok(record);
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&record)?;
+        encoder.finish()
+    }
+}
+
+/// Returns the SHA-1 digest of `bytes` as a WARC digest field gives it,
+/// labelled and in base 32.
+fn sha1_digest(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut digest = String::from("sha1:");
+    // Each 5 bytes make 8 characters, so the 20 bytes need no padding.
+    for chunk in Sha1::digest(bytes).chunks(5) {
+        let bits = chunk.iter().fold(0u64, |bits, &b| bits << 8 | u64::from(b));
+        for i in (0..8).rev() {
+            digest.push(ALPHABET[(bits >> (i * 5)) as usize & 31] as char);
+        }
+    }
+    digest
+}
+
+/// Returns a new `WARC-Record-ID`.
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", Uuid::new_v4())
+}
+
+/// Returns the message of `request`, sent over HTTP `version`,
+/// reconstructed from its URL and headers.
+fn http_request(request: &FetchRequest, version: Version) -> Vec<u8> {
+    let url = &request.url;
+    let mut target = url.path().to_owned();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let mut message = format!("{} {} {:?}\r\n", request.method, target, version).into_bytes();
+    if !request.headers.contains_key(HOST) {
+        let host = url.host_str().unwrap_or_default();
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_owned(),
+        };
+        message.extend_from_slice(format!("Host: {}\r\n", host).as_bytes());
+    }
+    write_headers(&mut message, &request.headers, version);
+    message.extend_from_slice(b"\r\n");
+    message
+}
+
+/// Returns the status line and headers of the message of `response`, up to
+/// where its body begins, reconstructed from its version, status and
+/// headers.
+fn http_response_head(response: &ResponseInfo) -> Vec<u8> {
+    let status = response.status;
+    let reason = status.canonical_reason().unwrap_or_default();
+    let mut message =
+        format!("{:?} {} {}\r\n", response.version, status.as_u16(), reason).into_bytes();
+    write_headers(&mut message, &response.headers, response.version);
+    message.extend_from_slice(b"\r\n");
+    message
+}
+
+/// Appends `headers` to `message`, other than `Transfer-Encoding`, with their
+/// names in the case HTTP `version` is sent in.
+fn write_headers(message: &mut Vec<u8>, headers: &HeaderMap, version: Version) {
+    let title_case = version < Version::HTTP_2;
+    for (name, value) in headers {
+        if name == TRANSFER_ENCODING {
+            continue;
+        }
+        let name = name.as_str().as_bytes();
+        if title_case {
+            // Each word of the name starts with a capital.
+            let mut capital = true;
+            for &b in name {
+                message.push(if capital { b.to_ascii_uppercase() } else { b });
+                capital = b == b'-';
+            }
+        } else {
+            message.extend_from_slice(name);
+        }
+        message.extend_from_slice(b": ");
+        message.extend_from_slice(value.as_bytes());
+        message.extend_from_slice(b"\r\n");
+    }
+}
+
+/// A time in UTC, to the second.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct UtcTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+}
+
+impl UtcTime {
+    /// The current time.
+    fn now() -> Self {
+        Self::from(SystemTime::now())
+    }
+
+    /// Formats the time as a `WARC-Date`, as in `2024-01-01T12:00:00Z`.
+    fn iso8601(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+
+    /// Formats the time for file names, as in `20240101120000`.
+    fn compact(&self) -> String {
+        format!(
+            "{:04}{:02}{:02}{:02}{:02}{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+impl From<SystemTime> for UtcTime {
+    /// Converts `time`, taking times before 1970 as 1970.
+    fn from(time: SystemTime) -> Self {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let (days, rest) = ((seconds / 86_400) as i64, (seconds % 86_400) as u32);
+        // The civil date of a day count, from Howard Hinnant's
+        // `civil_from_days`, with years beginning in March.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        Self {
+            year,
+            month,
+            day,
+            hour: rest / 3600,
+            minute: rest / 60 % 60,
+            second: rest % 60,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::read::{GzDecoder, MultiGzDecoder};
+    use reqwest::header::{HeaderValue, CONTENT_TYPE, USER_AGENT};
+    use reqwest::{Method, StatusCode, Url, Version};
+    use std::io::Read;
+    use std::time::Duration;
+
+    /// Returns an empty directory for the test `name`.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("warc-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Returns the paths of the files in `dir`, in name order.
+    fn paths(dir: &Path) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Returns the contents of every file in `dir`, in name order, decoded.
+    fn read_files(dir: &Path) -> Vec<String> {
+        paths(dir)
+            .iter()
+            .map(|path| {
+                let bytes = fs::read(path).unwrap();
+                if path.extension().unwrap() != "gz" {
+                    return String::from_utf8(bytes).unwrap();
+                }
+                let mut text = String::new();
+                MultiGzDecoder::new(&bytes[..])
+                    .read_to_string(&mut text)
+                    .unwrap();
+                text
+            })
+            .collect()
+    }
+
+    fn exchange(body: &str) -> (FetchRequest, ResponseInfo) {
+        let url = Url::parse("https://www.enhance.com:8443/about.html?q=1").unwrap();
+        let mut request = FetchRequest::new(Method::GET, url.clone());
+        request
+            .headers
+            .insert(USER_AGENT, HeaderValue::from_static("enhance-bot"));
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        let response = ResponseInfo {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            final_url: url,
+            redirects: Vec::new(),
+            content_type: Some("text/html".to_owned()),
+            content_length: Some(body.len() as u64),
+            headers,
+            elapsed: Duration::from_millis(1),
+        };
+        (request, response)
+    }
+
+    #[tokio::test]
+    async fn test_write_exchange() {
+        let dir = temp_dir("exchange");
+        let options = WarcOptions {
+            gzip: false,
+            ..WarcOptions::default()
+        };
+        let writer = WarcWriter::new(&dir, options);
+        let (request, response) = exchange("<p>Hi</p>");
+        let sent = UNIX_EPOCH + Duration::from_secs(951_827_696);
+        writer
+            .write_exchange(&request, &response, b"<p>Hi</p>", None, sent)
+            .await
+            .unwrap();
+
+        let files = read_files(&dir);
+        assert_eq!(files.len(), 1);
+        let records: Vec<&str> = files[0].split("WARC/1.1\r\n").skip(1).collect();
+        assert_eq!(records.len(), 3);
+        assert!(records[0].starts_with("WARC-Type: warcinfo\r\n"));
+        assert!(records[0].contains("\r\nWARC-Filename: crawl-"));
+        assert!(records[1].starts_with("WARC-Type: request\r\n"));
+        assert!(records[1].contains(
+            "Content-Type: application/http;msgtype=request\r\n\
+             Content-Length: 85\r\n\r\n\
+             GET /about.html?q=1 HTTP/1.1\r\n\
+             Host: www.enhance.com:8443\r\n\
+             User-Agent: enhance-bot\r\n\r\n\r\n\r\n"
+        ));
+        assert!(records[2].starts_with("WARC-Type: response\r\n"));
+        // Both are dated when the request was sent.
+        assert!(records[1].contains("\r\nWARC-Date: 2000-02-29T12:34:56Z\r\n"));
+        assert!(records[2].contains("\r\nWARC-Date: 2000-02-29T12:34:56Z\r\n"));
+        assert!(
+            records[2].contains("WARC-Target-URI: https://www.enhance.com:8443/about.html?q=1\r\n")
+        );
+        assert!(records[2]
+            .ends_with("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<p>Hi</p>\r\n\r\n"));
+        assert!(!records[2].contains("WARC-Truncated"));
+        // Digests are of the whole message, and of its body.
+        assert!(records[2]
+            .contains("\r\nWARC-Payload-Digest: sha1:ATQOZ6T6XGEIMQ42R6F7IMXCTVKIZL3O\r\n"));
+        assert!(
+            records[2].contains("\r\nWARC-Block-Digest: sha1:TBZPESOIUSZICPHRDWXJHGOU33UBJR7G\r\n")
+        );
+        assert!(records[1].contains("\r\nWARC-Block-Digest: sha1:"));
+
+        // The request is concurrent to the response, and both refer to the
+        // file's warcinfo record.
+        let field = |record: &str, name: &str| {
+            let start = record.find(&format!("{}: ", name)).unwrap() + name.len() + 2;
+            record[start..].split("\r\n").next().unwrap().to_owned()
+        };
+        assert_eq!(
+            field(records[1], "WARC-Concurrent-To"),
+            field(records[2], "WARC-Record-ID")
+        );
+        assert_eq!(
+            field(records[2], "WARC-Warcinfo-ID"),
+            field(records[0], "WARC-Record-ID")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_http2_messages() {
+        let (request, mut response) = exchange("<p>Hi</p>");
+        response.version = Version::HTTP_2;
+        let request = String::from_utf8(http_request(&request, response.version)).unwrap();
+        assert!(request.starts_with("GET /about.html?q=1 HTTP/2.0\r\n"));
+        assert!(request.contains("\r\nuser-agent: enhance-bot\r\n"));
+        let head = String::from_utf8(http_response_head(&response)).unwrap();
+        assert_eq!(head, "HTTP/2.0 200 OK\r\ncontent-type: text/html\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_gzip_rotation() {
+        let dir = temp_dir("rotation");
+        let options = WarcOptions {
+            prefix: "test".to_owned(),
+            max_file_size: Some(1),
+            gzip: true,
+        };
+        let writer = WarcWriter::new(&dir, options);
+        let (request, response) = exchange("");
+        for _ in 0..3 {
+            let truncated = Some(Truncated::Unspecified);
+            writer
+                .write_exchange(&request, &response, b"", truncated, SystemTime::now())
+                .await
+                .unwrap();
+        }
+
+        let files = read_files(&dir);
+        assert_eq!(files.len(), 3);
+        for file in files {
+            assert_eq!(file.matches("WARC/1.1\r\n").count(), 3);
+            assert!(file.contains("WARC-Truncated: unspecified\r\n"));
+        }
+        // Each record is a gzip member of its own, so the first member holds
+        // only the warcinfo record.
+        let paths = paths(&dir);
+        let name = paths[0].file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("test-") && name.ends_with("-00000.warc.gz"));
+        let mut first = String::new();
+        GzDecoder::new(&fs::read(&paths[0]).unwrap()[..])
+            .read_to_string(&mut first)
+            .unwrap();
+        assert!(first.starts_with("WARC/1.1\r\nWARC-Type: warcinfo\r\n"));
+        assert_eq!(first.matches("WARC/1.1\r\n").count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_utc_time() {
+        let time = |seconds| UtcTime::from(UNIX_EPOCH + Duration::from_secs(seconds));
+        assert_eq!(time(0).iso8601(), "1970-01-01T00:00:00Z");
+        assert_eq!(time(951_827_696).iso8601(), "2000-02-29T12:34:56Z");
+        assert_eq!(time(1_704_067_199).compact(), "20231231235959");
+    }
+}